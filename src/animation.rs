@@ -0,0 +1,226 @@
+// animation.rs
+
+use nalgebra_glm::Vec3;
+use crate::camera::Camera;
+
+// Posición de la cámara en un instante de la animación
+#[derive(Debug, Clone)]
+pub struct Keyframe {
+    pub time: f32, // Segundos
+    pub eye: Vec3,
+    pub center: Vec3,
+    pub up: Vec3,
+    pub fov: f32, // Radianes
+}
+
+// Cómo se unen los keyframes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    // Curva que pasa por todos los keyframes
+    CatmullRom,
+    // Bézier cúbicas encadenadas: los keyframes van en grupos de punto, control, control, punto,
+    // y la curva sólo pasa por los puntos. Los tiempos de los controles se ignoran.
+    Bezier,
+}
+
+impl Interpolation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "catmull-rom" | "catmull_rom" => Some(Interpolation::CatmullRom),
+            "bezier" => Some(Interpolation::Bezier),
+            _ => None,
+        }
+    }
+}
+
+// Estado interpolable de un keyframe: ojo, centro, arriba y campo de visión
+type State = [f32; 10];
+
+#[derive(Debug, Clone)]
+pub struct CameraAnimation {
+    keyframes: Vec<Keyframe>,
+    interpolation: Interpolation,
+}
+
+impl CameraAnimation {
+    // Falla si no hay keyframes, si los tiempos no son crecientes o si una animación Bézier no
+    // tiene 3n + 1 keyframes
+    pub fn new(keyframes: Vec<Keyframe>, interpolation: Interpolation) -> Result<Self, String> {
+        if keyframes.is_empty() {
+            return Err("a camera animation needs at least one keyframe".to_string());
+        }
+        if interpolation == Interpolation::Bezier && !(keyframes.len() - 1).is_multiple_of(3) {
+            return Err(format!(
+                "a bezier animation needs 3n + 1 keyframes (point, control, control, point, ...), found {}",
+                keyframes.len()
+            ));
+        }
+
+        let animation = CameraAnimation { keyframes, interpolation };
+        let anchors: Vec<f32> = animation.anchors().iter().map(|k| k.time).collect();
+        if anchors.windows(2).any(|pair| pair[1] <= pair[0]) {
+            return Err("keyframe times must be strictly increasing".to_string());
+        }
+
+        Ok(animation)
+    }
+
+    pub fn start(&self) -> f32 {
+        self.keyframes[0].time
+    }
+
+    pub fn end(&self) -> f32 {
+        self.keyframes[self.keyframes.len() - 1].time
+    }
+
+    // Cámara en el instante `time`. El resto de parámetros (proyección, apertura, ...) se copian
+    // de `base`. Fuera del rango de la animación la cámara se queda en el extremo más cercano.
+    pub fn camera_at(&self, base: &Camera, time: f32) -> Camera {
+        let state = match self.interpolation {
+            Interpolation::CatmullRom => self.catmull_rom_at(time),
+            Interpolation::Bezier => self.bezier_at(time),
+        };
+
+        let mut camera = base.clone();
+        camera.eye = Vec3::new(state[0], state[1], state[2]);
+        camera.center = Vec3::new(state[3], state[4], state[5]);
+        camera.up = Vec3::new(state[6], state[7], state[8]).normalize();
+        camera.fov = state[9];
+        camera
+    }
+
+    // Keyframes por los que pasa la curva
+    fn anchors(&self) -> Vec<&Keyframe> {
+        let step = match self.interpolation {
+            Interpolation::CatmullRom => 1,
+            Interpolation::Bezier => 3,
+        };
+        self.keyframes.iter().step_by(step).collect()
+    }
+
+    // Segmento que contiene `time` entre los puntos `times` y posición local en [0, 1]
+    fn segment(times: &[f32], time: f32) -> (usize, f32) {
+        if times.len() < 2 || time <= times[0] {
+            return (0, 0.0);
+        }
+        let last = times.len() - 2;
+        let index = times.windows(2).position(|pair| time < pair[1]).unwrap_or(last);
+        let t = ((time - times[index]) / (times[index + 1] - times[index])).clamp(0.0, 1.0);
+        (index, t)
+    }
+
+    fn catmull_rom_at(&self, time: f32) -> State {
+        let states: Vec<State> = self.keyframes.iter().map(state_of).collect();
+        if states.len() == 1 {
+            return states[0];
+        }
+
+        let times: Vec<f32> = self.keyframes.iter().map(|k| k.time).collect();
+        let (i, t) = Self::segment(&times, time);
+
+        // Los extremos se repiten para que la curva empiece y termine en el primer y último keyframe
+        let p0 = states[i.saturating_sub(1)];
+        let p1 = states[i];
+        let p2 = states[i + 1];
+        let p3 = states[(i + 2).min(states.len() - 1)];
+
+        combine(&[p0, p1, p2, p3], |k| {
+            let (t2, t3) = (t * t, t * t * t);
+            match k {
+                0 => 0.5 * (-t + 2.0 * t2 - t3),
+                1 => 0.5 * (2.0 - 5.0 * t2 + 3.0 * t3),
+                2 => 0.5 * (t + 4.0 * t2 - 3.0 * t3),
+                _ => 0.5 * (-t2 + t3),
+            }
+        })
+    }
+
+    fn bezier_at(&self, time: f32) -> State {
+        let states: Vec<State> = self.keyframes.iter().map(state_of).collect();
+        if states.len() == 1 {
+            return states[0];
+        }
+
+        let times: Vec<f32> = self.anchors().iter().map(|k| k.time).collect();
+        let (segment, t) = Self::segment(&times, time);
+        let i = segment * 3;
+
+        combine(&states[i..i + 4], |k| {
+            let s = 1.0 - t;
+            match k {
+                0 => s * s * s,
+                1 => 3.0 * s * s * t,
+                2 => 3.0 * s * t * t,
+                _ => t * t * t,
+            }
+        })
+    }
+}
+
+fn state_of(keyframe: &Keyframe) -> State {
+    let (e, c, u) = (keyframe.eye, keyframe.center, keyframe.up);
+    [e.x, e.y, e.z, c.x, c.y, c.z, u.x, u.y, u.z, keyframe.fov]
+}
+
+// Suma de los estados ponderada por `weight(índice)`
+fn combine<F: Fn(usize) -> f32>(states: &[State], weight: F) -> State {
+    let mut result = [0.0; 10];
+    for (k, state) in states.iter().enumerate() {
+        let w = weight(k);
+        for (out, value) in result.iter_mut().zip(state) {
+            *out += w * value;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f32, x: f32) -> Keyframe {
+        Keyframe {
+            time,
+            eye: Vec3::new(x, 0.0, 10.0),
+            center: Vec3::zeros(),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            fov: 1.0,
+        }
+    }
+
+    fn base() -> Camera {
+        Camera::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_every_keyframe() {
+        let keyframes = vec![keyframe(0.0, 0.0), keyframe(1.0, 4.0), keyframe(3.0, -2.0), keyframe(4.0, 1.0)];
+        let animation = CameraAnimation::new(keyframes.clone(), Interpolation::CatmullRom).unwrap();
+
+        for k in &keyframes {
+            let camera = animation.camera_at(&base(), k.time);
+            assert!((camera.eye - k.eye).norm() < 1e-5, "t = {}: {:?}", k.time, camera.eye);
+        }
+
+        // Antes del principio y después del final se queda en los extremos
+        assert!((animation.camera_at(&base(), -1.0).eye - keyframes[0].eye).norm() < 1e-5);
+        assert!((animation.camera_at(&base(), 9.0).eye - keyframes[3].eye).norm() < 1e-5);
+    }
+
+    #[test]
+    fn bezier_passes_through_anchors_only() {
+        let keyframes = vec![keyframe(0.0, 0.0), keyframe(0.0, 6.0), keyframe(0.0, 6.0), keyframe(2.0, 0.0)];
+        let animation = CameraAnimation::new(keyframes, Interpolation::Bezier).unwrap();
+
+        assert!(animation.camera_at(&base(), 0.0).eye.x.abs() < 1e-5);
+        assert!(animation.camera_at(&base(), 2.0).eye.x.abs() < 1e-5);
+        // A mitad de camino: 0.375 * 6 + 0.375 * 6
+        assert!((animation.camera_at(&base(), 1.0).eye.x - 4.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bezier_rejects_incomplete_segments() {
+        let keyframes = vec![keyframe(0.0, 0.0), keyframe(1.0, 1.0), keyframe(2.0, 2.0)];
+        assert!(CameraAnimation::new(keyframes, Interpolation::Bezier).is_err());
+    }
+}
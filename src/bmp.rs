@@ -5,7 +5,12 @@ use std::io::{Write, BufWriter, Result};
 
 const BMP_HEADER_SIZE: usize = 54;
 const BMP_PIXEL_OFFSET: usize = 54;
-const BMP_BITS_PER_PIXEL: usize = 32;
+const BMP_BITS_PER_PIXEL: usize = 24;
+
+// Cada fila ocupa 3 bytes por píxel, rellenada hasta un múltiplo de 4
+fn row_size(width: usize) -> usize {
+    (width * 3 + 3) & !3
+}
 
 pub fn write_bmp_file(
     file_path: &str,
@@ -28,7 +33,7 @@ fn write_bmp_header(
     width: usize,
     height: usize,
 ) -> Result<()> {
-    let file_size = (BMP_HEADER_SIZE + (width * height * 4)) as u32;
+    let file_size = (BMP_HEADER_SIZE + row_size(width) * height) as u32;
     let reserved: u32 = 0;
     let offset: u32 = BMP_PIXEL_OFFSET as u32;
 
@@ -43,7 +48,7 @@ fn write_bmp_header(
     let planes: u16 = 1;
     let bpp: u16 = BMP_BITS_PER_PIXEL as u16;
     let compression: u32 = 0;
-    let image_size: u32 = (width * height * 4) as u32;
+    let image_size: u32 = (row_size(width) * height) as u32;
     let ppm: u32 = 2835; // 72 DPI
 
     writer.write_all(&header_size.to_le_bytes())?;
@@ -67,15 +72,18 @@ fn write_pixel_data(
     width: usize,
     height: usize,
 ) -> Result<()> {
+    let padding = [0u8; 3];
+    let padding_len = row_size(width) - width * 3;
+
     for y in (0..height).rev() {
         for x in 0..width {
             let pixel = buffer[y * width + x];
             let b = (pixel & 0xFF) as u8;
             let g = ((pixel >> 8) & 0xFF) as u8;
             let r = ((pixel >> 16) & 0xFF) as u8;
-            let a = ((pixel >> 24) & 0xFF) as u8;
-            writer.write_all(&[b, g, r, a])?;
+            writer.write_all(&[b, g, r])?;
         }
+        writer.write_all(&padding[..padding_len])?;
     }
     Ok(())
 }
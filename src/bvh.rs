@@ -0,0 +1,410 @@
+// bvh.rs
+
+use nalgebra_glm::{Vec3, min2, max2};
+use crate::intersect::Intersect;
+use crate::ray_intersect::Renderable;
+
+const SAH_BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f32 = 1.0;
+const INTERSECTION_COST: f32 = 1.0;
+
+// Caja alineada a los ejes usada como volumen envolvente
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb { min, max }
+    }
+
+    // Caja "invertida" que actúa como elemento neutro de `union`
+    pub fn empty() -> Self {
+        Aabb {
+            min: Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: min2(&self.min, &other.min),
+            max: max2(&self.max, &other.max),
+        }
+    }
+
+    pub fn grow(&self, point: &Vec3) -> Aabb {
+        Aabb {
+            min: min2(&self.min, point),
+            max: max2(&self.max, point),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let extent = self.max - self.min;
+        if extent.x < 0.0 || extent.y < 0.0 || extent.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    // Prueba de "slabs": devuelve la distancia de entrada si el rayo toca la caja antes de `max_distance`
+    pub fn hit(&self, ray_origin: &Vec3, inv_dir: &Vec3, max_distance: f32) -> Option<f32> {
+        let mut tmin: f32 = 0.0;
+        let mut tmax = max_distance;
+
+        for axis in 0..3 {
+            let t1 = (self.min[axis] - ray_origin[axis]) * inv_dir[axis];
+            let t2 = (self.max[axis] - ray_origin[axis]) * inv_dir[axis];
+
+            // `min`/`max` descartan los NaN producidos por 0 * infinito
+            tmin = tmin.max(t1.min(t2));
+            tmax = tmax.min(t1.max(t2));
+        }
+
+        if tmin <= tmax {
+            Some(tmin)
+        } else {
+            None
+        }
+    }
+}
+
+struct BvhNode {
+    bounds: Aabb,
+    // Para hojas: índice del primer objeto en `indices`. Para nodos internos: índice del hijo izquierdo
+    // (el derecho siempre es `first + 1`).
+    first: usize,
+    count: usize,
+}
+
+impl BvhNode {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+// Jerarquía de volúmenes envolventes construida con la heurística de área superficial (SAH)
+pub struct Bvh {
+    objects: Vec<Box<dyn Renderable>>,
+    indices: Vec<usize>,
+    nodes: Vec<BvhNode>,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Box<dyn Renderable>>) -> Self {
+        let bounds: Vec<Aabb> = objects.iter().map(|object| object.bounding_box()).collect();
+        let centroids: Vec<Vec3> = bounds.iter().map(|b| b.centroid()).collect();
+
+        let mut bvh = Bvh {
+            indices: (0..objects.len()).collect(),
+            objects,
+            nodes: Vec::new(),
+        };
+
+        if !bvh.objects.is_empty() {
+            bvh.nodes.push(BvhNode { bounds: Aabb::empty(), first: 0, count: bvh.objects.len() });
+            bvh.subdivide(0, &bounds, &centroids);
+        }
+
+        bvh
+    }
+
+    fn subdivide(&mut self, node_index: usize, bounds: &[Aabb], centroids: &[Vec3]) {
+        let first = self.nodes[node_index].first;
+        let count = self.nodes[node_index].count;
+        let range = first..first + count;
+
+        let node_bounds = self.indices[range.clone()]
+            .iter()
+            .fold(Aabb::empty(), |acc, &i| acc.union(&bounds[i]));
+        self.nodes[node_index].bounds = node_bounds;
+
+        if count <= 1 {
+            return;
+        }
+
+        let centroid_bounds = self.indices[range.clone()]
+            .iter()
+            .fold(Aabb::empty(), |acc, &i| acc.grow(&centroids[i]));
+
+        // Buscar el mejor plano de corte evaluando la SAH sobre cubetas en cada eje
+        let mut best: Option<(usize, usize, f32)> = None; // (eje, cubeta, costo)
+        for (axis, (&lower, &upper)) in centroid_bounds.min.iter().zip(centroid_bounds.max.iter()).enumerate() {
+            let extent = upper - lower;
+            if extent <= f32::EPSILON {
+                continue;
+            }
+
+            let mut bin_bounds = [Aabb::empty(); SAH_BINS];
+            let mut bin_counts = [0usize; SAH_BINS];
+            for &i in &self.indices[range.clone()] {
+                let bin = bin_for(centroids[i][axis], lower, extent);
+                bin_bounds[bin] = bin_bounds[bin].union(&bounds[i]);
+                bin_counts[bin] += 1;
+            }
+
+            // Barrido de derecha a izquierda para acumular las áreas del lado derecho
+            let mut right_area = [0.0f32; SAH_BINS];
+            let mut right_count = [0usize; SAH_BINS];
+            let mut acc_bounds = Aabb::empty();
+            let mut acc_count = 0;
+            for bin in (1..SAH_BINS).rev() {
+                acc_bounds = acc_bounds.union(&bin_bounds[bin]);
+                acc_count += bin_counts[bin];
+                right_area[bin] = acc_bounds.surface_area();
+                right_count[bin] = acc_count;
+            }
+
+            let mut acc_bounds = Aabb::empty();
+            let mut acc_count = 0;
+            for split in 1..SAH_BINS {
+                acc_bounds = acc_bounds.union(&bin_bounds[split - 1]);
+                acc_count += bin_counts[split - 1];
+                if acc_count == 0 || right_count[split] == 0 {
+                    continue;
+                }
+
+                let cost = acc_bounds.surface_area() * acc_count as f32
+                    + right_area[split] * right_count[split] as f32;
+                if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best = Some((axis, split, cost));
+                }
+            }
+        }
+
+        let parent_area = node_bounds.surface_area().max(f32::EPSILON);
+        let leaf_cost = INTERSECTION_COST * count as f32;
+
+        let (axis, split) = match best {
+            Some((axis, split, cost))
+                if count > MAX_LEAF_SIZE
+                    || TRAVERSAL_COST + INTERSECTION_COST * cost / parent_area < leaf_cost =>
+            {
+                (axis, split)
+            }
+            // Todos los centroides coinciden o dividir no compensa: dejar la hoja como está
+            _ => return,
+        };
+
+        let lower = centroid_bounds.min[axis];
+        let extent = centroid_bounds.max[axis] - lower;
+
+        // Particionar los índices en el lugar según la cubeta elegida
+        let mut left = first;
+        let mut right = first + count;
+        while left < right {
+            if bin_for(centroids[self.indices[left]][axis], lower, extent) < split {
+                left += 1;
+            } else {
+                right -= 1;
+                self.indices.swap(left, right);
+            }
+        }
+
+        let left_count = left - first;
+        if left_count == 0 || left_count == count {
+            return;
+        }
+
+        let left_child = self.nodes.len();
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), first, count: left_count });
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), first: left, count: count - left_count });
+
+        self.nodes[node_index].first = left_child;
+        self.nodes[node_index].count = 0;
+
+        self.subdivide(left_child, bounds, centroids);
+        self.subdivide(left_child + 1, bounds, centroids);
+    }
+
+    // Intersección más cercana del rayo con la escena
+    pub fn intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        let mut closest = Intersect::empty();
+        let mut zbuffer = f32::INFINITY;
+
+        self.traverse(ray_origin, ray_direction, |object| {
+            let tmp = object.ray_intersect(ray_origin, ray_direction);
+            if tmp.is_intersecting && tmp.distance < zbuffer {
+                zbuffer = tmp.distance;
+                closest = tmp;
+            }
+            (zbuffer, false)
+        });
+
+        closest
+    }
+
+    // Indica si algún objeto bloquea el rayo antes de `max_distance` (para rayos de sombra)
+    pub fn occluded(&self, ray_origin: &Vec3, ray_direction: &Vec3, max_distance: f32) -> bool {
+        let mut blocked = false;
+
+        self.traverse(ray_origin, ray_direction, |object| {
+            let tmp = object.ray_intersect(ray_origin, ray_direction);
+            blocked = tmp.is_intersecting && tmp.distance < max_distance;
+            (max_distance, blocked)
+        });
+
+        blocked
+    }
+
+    // Recorre los nodos que el rayo toca, del más cercano al más lejano. `visit` devuelve la
+    // distancia máxima que aún interesa y si se debe detener el recorrido.
+    fn traverse<F>(&self, ray_origin: &Vec3, ray_direction: &Vec3, mut visit: F)
+    where
+        F: FnMut(&dyn Renderable) -> (f32, bool),
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inv_dir = Vec3::new(1.0 / ray_direction.x, 1.0 / ray_direction.y, 1.0 / ray_direction.z);
+        let mut max_distance = f32::INFINITY;
+
+        if self.nodes[0].bounds.hit(ray_origin, &inv_dir, max_distance).is_none() {
+            return;
+        }
+
+        let mut stack: Vec<(usize, f32)> = Vec::with_capacity(64);
+        stack.push((0, 0.0));
+
+        while let Some((node_index, entry)) = stack.pop() {
+            // El nodo pudo quedar detrás de un impacto encontrado después de apilarlo
+            if entry > max_distance {
+                continue;
+            }
+            let node = &self.nodes[node_index];
+
+            if node.is_leaf() {
+                for &i in &self.indices[node.first..node.first + node.count] {
+                    let (distance, stop) = visit(self.objects[i].as_ref());
+                    if stop {
+                        return;
+                    }
+                    max_distance = distance;
+                }
+                continue;
+            }
+
+            let left = node.first;
+            let right = node.first + 1;
+            let hit_left = self.nodes[left].bounds.hit(ray_origin, &inv_dir, max_distance);
+            let hit_right = self.nodes[right].bounds.hit(ray_origin, &inv_dir, max_distance);
+
+            // Apilar primero el hijo más lejano para visitar antes el más cercano
+            match (hit_left, hit_right) {
+                (Some(t_left), Some(t_right)) => {
+                    if t_left <= t_right {
+                        stack.push((right, t_right));
+                        stack.push((left, t_left));
+                    } else {
+                        stack.push((left, t_left));
+                        stack.push((right, t_right));
+                    }
+                }
+                (Some(t_left), None) => stack.push((left, t_left)),
+                (None, Some(t_right)) => stack.push((right, t_right)),
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+fn bin_for(value: f32, lower: f32, extent: f32) -> usize {
+    let bin = ((value - lower) / extent * SAH_BINS as f32) as usize;
+    bin.min(SAH_BINS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::figures::{Cube, Sphere};
+    use crate::material::Material;
+    use crate::sampling::Rng;
+    use std::sync::Arc;
+
+    // Material distinto por objeto, para saber cuál tocó el rayo
+    fn tagged(index: usize) -> Material {
+        Material::new(Color::rgb(index as f32, 0.0, 0.0), 0.0, [1.0, 0.0, 0.0, 0.0], 1.0, Default::default(), false)
+    }
+
+    fn random_point(rng: &mut Rng, extent: f32) -> Vec3 {
+        Vec3::new(
+            (rng.next_f32() * 2.0 - 1.0) * extent,
+            (rng.next_f32() * 2.0 - 1.0) * extent,
+            (rng.next_f32() * 2.0 - 1.0) * extent,
+        )
+    }
+
+    fn random_objects(rng: &mut Rng, count: usize) -> Vec<Box<dyn Renderable>> {
+        (0..count)
+            .map(|i| -> Box<dyn Renderable> {
+                let center = random_point(rng, 10.0);
+                if i % 2 == 0 {
+                    let length = 1 + (rng.next_f32() * 3.0) as i16;
+                    Box::new(Cube { center, length, material: Arc::new(tagged(i)) })
+                } else {
+                    Box::new(Sphere { center, radius: 0.3 + rng.next_f32() * 1.2, material: tagged(i) })
+                }
+            })
+            .collect()
+    }
+
+    // Intersección más cercana probando todos los objetos, sin jerarquía
+    fn linear_intersect(objects: &[Box<dyn Renderable>], origin: &Vec3, direction: &Vec3) -> Intersect {
+        let mut closest = Intersect::empty();
+        for object in objects {
+            let hit = object.ray_intersect(origin, direction);
+            if hit.is_intersecting && (!closest.is_intersecting || hit.distance < closest.distance) {
+                closest = hit;
+            }
+        }
+        closest
+    }
+
+    fn tag(intersect: &Intersect) -> f32 {
+        intersect.material.as_ref().map_or(-1.0, |material| material.diffuse.r())
+    }
+
+    #[test]
+    fn intersect_and_occluded_match_a_linear_scan() {
+        let mut rng = Rng::new(7);
+        let objects = random_objects(&mut rng, 60);
+        // Una copia para la búsqueda lineal: el BVH se queda con los objetos
+        let reference = random_objects(&mut Rng::new(7), 60);
+        let bvh = Bvh::new(objects);
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = random_point(&mut rng, 15.0);
+            let direction = (random_point(&mut rng, 1.0) + Vec3::new(1e-3, 0.0, 0.0)).normalize();
+
+            let expected = linear_intersect(&reference, &origin, &direction);
+            let actual = bvh.intersect(&origin, &direction);
+            assert_eq!(actual.is_intersecting, expected.is_intersecting);
+            if expected.is_intersecting {
+                hits += 1;
+                assert_eq!(actual.distance, expected.distance);
+                assert_eq!(tag(&actual), tag(&expected));
+            }
+
+            let max_distance = rng.next_f32() * 20.0;
+            let blocked = reference.iter().any(|object| {
+                let hit = object.ray_intersect(&origin, &direction);
+                hit.is_intersecting && hit.distance < max_distance
+            });
+            assert_eq!(bvh.occluded(&origin, &direction, max_distance), blocked);
+        }
+
+        // Que la prueba no pase sólo porque los rayos no tocan nada
+        assert!(hits > 200, "only {} rays hit something", hits);
+    }
+}
@@ -1,22 +1,280 @@
-use nalgebra_glm::{Vec3, normalize, cross, magnitude};
+use nalgebra_glm::Vec3;
 use std::f32::consts::PI;
 
+// Eje de la imagen al que se refiere el campo de visión
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FovAxis {
+    Vertical,
+    Horizontal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // Rayos paralelos a la dirección de la cámara; el tamaño de la vista es `ortho_width`
+    Orthographic,
+    // Panorama de 360° en latitud-longitud, para imágenes 2:1 que se ven en visores de VR.
+    // El centro de la imagen mira hacia `center`.
+    Equirectangular,
+    // Las seis caras de un cubo alrededor del ojo en una cuadrícula de 3x2, en el mismo orden
+    // que las texturas: derecha, izquierda, arriba / abajo, frente, atrás. Cada cara es un
+    // cuadrado con 90° de campo de visión.
+    Cubemap,
+}
+
+impl Projection {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic),
+            "equirectangular" => Some(Projection::Equirectangular),
+            "cubemap" => Some(Projection::Cubemap),
+            _ => None,
+        }
+    }
+}
+
+// Proyección elegida por nombre en la escena o con `--projection`. "isometric" no es una
+// proyección aparte: es la ortográfica con la cámara colocada en diagonal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionChoice {
+    Projection(Projection),
+    Isometric,
+}
+
+impl ProjectionChoice {
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("isometric") {
+            Some(ProjectionChoice::Isometric)
+        } else {
+            Projection::from_name(name).map(ProjectionChoice::Projection)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Camera {
     pub eye: Vec3,  // Posición de la cámara en el espacio mundial
     pub center: Vec3, // Punto que la cámara está mirando
     pub up: Vec3,  // Vector hacia arriba
+    pub fov: f32, // Campo de visión completo en radianes, medido sobre `fov_axis`
+    pub fov_axis: FovAxis,
+    pub near: f32, // Distancia del plano cercano; lo que esté más cerca no se ve
+    pub projection: Projection,
+    pub ortho_width: f32, // Ancho horizontal de la vista ortográfica en unidades del mundo
+    pub aperture: f32, // Radio de la lente; 0 es una cámara estenopeica, todo enfocado
+    pub focus_distance: f32, // Distancia del plano de enfoque, medida a lo largo de la vista
+    pub shift_x: f32, // Desplazamiento horizontal de la imagen, para encuadres descentrados (a distancia 1 en perspectiva)
+}
+
+// Cómo se combinan las imágenes de los dos ojos
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoMode {
+    // Ojo izquierdo en la mitad izquierda de la imagen y derecho en la derecha
+    SideBySide,
+    // Rojo del ojo izquierdo, verde y azul del derecho, para gafas rojo/cian
+    Anaglyph,
+}
+
+impl StereoMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "side-by-side" | "sbs" => Some(StereoMode::SideBySide),
+            "anaglyph" => Some(StereoMode::Anaglyph),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stereo {
+    pub mode: StereoMode,
+    pub interocular: f32, // Separación entre los ojos
+    pub convergence: f32, // Distancia a la que las dos imágenes coinciden (la "pantalla")
+}
+
+impl Stereo {
+    pub fn new(mode: StereoMode, interocular: f32, convergence: f32) -> Self {
+        Stereo { mode, interocular, convergence }
+    }
+
+    // Cámaras de los ojos izquierdo y derecho. En perspectiva los ojos miran en paralelo y cada
+    // imagen se desplaza para que converjan a `convergence` (proyección descentrada), lo que
+    // evita la paralaje vertical de girar los ojos hacia dentro. En ortográfica desplazar el ojo
+    // sólo mueve la imagen entera, así que los ojos sí giran hacia el punto de convergencia. Las
+    // proyecciones panorámicas no están soportadas (ver `supports`).
+    pub fn eyes(&self, camera: &Camera) -> (Camera, Camera) {
+        let forward = (camera.center - camera.eye).normalize();
+        let right = forward.cross(&camera.up).normalize();
+        let half = self.interocular / 2.0;
+        let convergence = self.convergence.max(1e-3);
+        let shift = half / convergence;
+
+        let eye = |side: f32| {
+            let mut eye = camera.clone();
+            eye.eye += right * (half * side);
+            if camera.projection == Projection::Orthographic {
+                eye.center = camera.eye + forward * convergence;
+            } else {
+                eye.center += right * (half * side);
+                eye.shift_x -= shift * side;
+            }
+            eye
+        };
+
+        (eye(-1.0), eye(1.0))
+    }
+
+    // Un panorama estéreo necesitaría desplazar el ojo de forma distinta en cada dirección
+    pub fn supports(projection: Projection) -> bool {
+        matches!(projection, Projection::Perspective | Projection::Orthographic)
+    }
 }
 
 impl Camera {
+    // Cámara con 90° de campo de visión vertical y sin plano cercano, como hasta ahora
+    pub fn new(eye: Vec3, center: Vec3, up: Vec3) -> Self {
+        Camera {
+            eye,
+            center,
+            up,
+            fov: PI / 2.0,
+            fov_axis: FovAxis::Vertical,
+            near: 0.0,
+            projection: Projection::Perspective,
+            ortho_width: 10.0,
+            aperture: 0.0,
+            focus_distance: (eye - center).magnitude(),
+            shift_x: 0.0,
+        }
+    }
+
+    // Cambia la proyección conservando el encuadre: la vista ortográfica toma el tamaño que
+    // tiene la perspectiva a la distancia de `center`
+    pub fn set_projection(&mut self, projection: Projection, aspect_ratio: f32) {
+        if projection == Projection::Orthographic && self.projection == Projection::Perspective {
+            let (half_width, _) = self.half_extents(aspect_ratio);
+            self.ortho_width = 2.0 * half_width * (self.eye - self.center).magnitude();
+        }
+        self.projection = projection;
+    }
+
+    pub fn apply_projection(&mut self, choice: ProjectionChoice, aspect_ratio: f32) {
+        match choice {
+            ProjectionChoice::Projection(projection) => self.set_projection(projection, aspect_ratio),
+            ProjectionChoice::Isometric => self.make_isometric(aspect_ratio),
+        }
+    }
+
+    // Vista isométrica clásica: ortográfica, mirando a `center` en diagonal desde arriba
+    // (45° de giro y unos 35.26° de inclinación), a la misma distancia que antes
+    pub fn make_isometric(&mut self, aspect_ratio: f32) {
+        let distance = (self.eye - self.center).magnitude();
+        self.set_projection(Projection::Orthographic, aspect_ratio);
+        self.eye = self.center + Vec3::new(1.0, 1.0, 1.0).normalize() * distance;
+        self.up = Vec3::new(0.0, 1.0, 0.0);
+    }
+
+    // Mitad del ancho y del alto de la imagen a distancia 1 de la cámara en perspectiva
+    fn half_extents(&self, aspect_ratio: f32) -> (f32, f32) {
+        let half_extent = (self.fov / 2.0).tan();
+        match self.fov_axis {
+            FovAxis::Vertical => (half_extent * aspect_ratio, half_extent),
+            FovAxis::Horizontal => (half_extent, half_extent / aspect_ratio),
+        }
+    }
+
     pub fn basis_change(&self, vector: &Vec3) -> Vec3 {
         let forward = (self.center - self.eye).normalize();
         let right = forward.cross(&self.up).normalize();
         let up = right.cross(&forward).normalize();
 
         // Convertir la dirección del rayo del espacio de la cámara al espacio del mundo
-        let rotated = vector.x * right + vector.y * up - vector.z * forward;
+        vector.x * right + vector.y * up - vector.z * forward
+    }
 
-        rotated
+    // Rayo primario (origen, dirección normalizada) que pasa por el punto `(x, y)` de una
+    // imagen de `width` x `height` píxeles, con `(0, 0)` en la esquina superior izquierda.
+    // El campo de visión se aplica al eje elegido y el otro se ajusta a la relación de aspecto,
+    // así que los píxeles siempre son cuadrados. `lens` es una muestra en [0, 1)^2 de la
+    // apertura; con `aperture == 0` no se usa.
+    pub fn primary_ray(&self, x: f32, y: f32, width: usize, height: usize, lens: (f32, f32)) -> (Vec3, Vec3) {
+        // Las proyecciones panorámicas no tienen un plano de imagen: todos los rayos salen del
+        // ojo y no hay profundidad de campo
+        if let Some(direction) = self.panoramic_direction(x / width as f32, y / height as f32) {
+            let direction = self.basis_change(&direction).normalize();
+            return (self.eye + direction * self.near, direction);
+        }
+
+        let aspect_ratio = width as f32 / height as f32;
+        let (half_width, half_height) = match self.projection {
+            Projection::Orthographic => (self.ortho_width / 2.0, self.ortho_width / 2.0 / aspect_ratio),
+            _ => self.half_extents(aspect_ratio),
+        };
+
+        let screen_x = ((2.0 * x) / width as f32 - 1.0) * half_width + self.shift_x;
+        let screen_y = -((2.0 * y) / height as f32 - 1.0) * half_height;
+
+        // Rayo en el espacio de la cámara. En perspectiva sale del ojo hacia el punto de la
+        // imagen a distancia 1; en ortográfica todos van en la dirección de la vista, desde el
+        // plano de la imagen.
+        let (mut origin, mut direction) = match self.projection {
+            Projection::Orthographic => (Vec3::new(screen_x, screen_y, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+            _ => (Vec3::zeros(), Vec3::new(screen_x, screen_y, -1.0)),
+        };
+
+        // Lente delgada: el rayo sale de un punto del disco de la apertura y pasa por el mismo
+        // punto del plano de enfoque, que es lo único que queda nítido
+        if self.aperture > 0.0 {
+            let focus_point = origin + direction * self.focus_distance;
+            let radius = self.aperture * lens.0.sqrt();
+            let theta = 2.0 * PI * lens.1;
+            origin += Vec3::new(radius * theta.cos(), radius * theta.sin(), 0.0);
+            direction = focus_point - origin;
+        }
+
+        // El rayo empieza donde cruza el plano cercano
+        origin += direction * (self.near / -direction.z);
+
+        (self.eye + self.basis_change(&origin), self.basis_change(&direction).normalize())
+    }
+
+    // Dirección en el espacio de la cámara para el punto `(u, v)` de la imagen, ambos en [0, 1],
+    // o `None` si la proyección no es panorámica
+    fn panoramic_direction(&self, u: f32, v: f32) -> Option<Vec3> {
+        match self.projection {
+            Projection::Equirectangular => {
+                let longitude = (u - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - v) * PI;
+                Some(Vec3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                ))
+            }
+            Projection::Cubemap => {
+                let column = ((u * 3.0) as usize).min(2);
+                let row = ((v * 2.0) as usize).min(1);
+                // Coordenadas dentro de la cara: `a` hacia la derecha y `b` hacia arriba
+                let a = (u * 3.0 - column as f32) * 2.0 - 1.0;
+                let b = 1.0 - (v * 2.0 - row as f32) * 2.0;
+                Some(match row * 3 + column {
+                    0 => Vec3::new(1.0, b, a),   // Derecha
+                    1 => Vec3::new(-1.0, b, -a), // Izquierda
+                    2 => Vec3::new(a, 1.0, b),   // Arriba
+                    3 => Vec3::new(a, -1.0, -b), // Abajo
+                    4 => Vec3::new(a, b, -1.0),  // Frente
+                    _ => Vec3::new(-a, b, 1.0),  // Atrás
+                })
+            }
+            Projection::Perspective | Projection::Orthographic => None,
+        }
+    }
+
+    // Distancia a lo largo de la vista hasta `point`, la que hay que usar como `focus_distance`
+    // para enfocarlo
+    pub fn depth_of(&self, point: &Vec3) -> f32 {
+        (point - self.eye).dot(&(self.center - self.eye).normalize())
     }
 
     pub fn orbit(&mut self, delta_yaw: f32, delta_pitch: f32) {
@@ -45,9 +303,34 @@ impl Camera {
         self.eye = new_eye;
     }
 
-    pub fn get_yaw(&self) -> f32 {
+    // Gira la vista alrededor del ojo, como mover la cabeza. El ángulo vertical se limita para
+    // no pasar por encima de la vertical.
+    pub fn look(&mut self, delta_yaw: f32, delta_pitch: f32) {
         let direction = self.center - self.eye;
-        direction.z.atan2(direction.x)
+        let distance = direction.magnitude();
+
+        let yaw = direction.z.atan2(direction.x) + delta_yaw;
+        let radius_xz = (direction.x * direction.x + direction.z * direction.z).sqrt();
+        let pitch = (direction.y.atan2(radius_xz) + delta_pitch).clamp(-PI / 2.0 + 0.01, PI / 2.0 - 0.01);
+
+        self.center = self.eye + Vec3::new(
+            distance * yaw.cos() * pitch.cos(),
+            distance * pitch.sin(),
+            distance * yaw.sin() * pitch.cos()
+        );
+    }
+
+    // Desplaza el ojo y el punto mirado a la vez: `forward` y `right` sobre el plano horizontal,
+    // como al caminar, y `up` en vertical
+    pub fn fly(&mut self, forward: f32, right: f32, up: f32) {
+        let direction = self.center - self.eye;
+        let flat_forward = Vec3::new(direction.x, 0.0, direction.z);
+        let flat_forward = if flat_forward.magnitude() > 1e-6 { flat_forward.normalize() } else { Vec3::zeros() };
+        let flat_right = Vec3::new(-flat_forward.z, 0.0, flat_forward.x);
+
+        let offset = flat_forward * forward + flat_right * right + Vec3::new(0.0, up, 0.0);
+        self.eye += offset;
+        self.center += offset;
     }
 
     pub fn get_pitch(&self) -> f32 {
@@ -56,7 +339,14 @@ impl Camera {
         (-direction.y).atan2(radius_xz)
     }
 
+    // En perspectiva acerca o aleja el ojo; en ortográfica cambia el tamaño de la vista, ya que
+    // mover el ojo no cambiaría la imagen
     pub fn zoom (&mut self, delta: f32) {
+        if self.projection == Projection::Orthographic {
+            self.ortho_width = (self.ortho_width * (1.0 + delta * 0.1)).max(0.1);
+            return;
+        }
+
         let direction = (self.eye - self.center).normalize();
 
         let new_eye = self.eye + direction * delta;
@@ -68,3 +358,102 @@ impl Camera {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mira hacia -z con +x a la derecha y +y arriba, así las direcciones del mundo coinciden
+    // con las de la cámara
+    fn camera(projection: Projection) -> Camera {
+        let mut camera = Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0));
+        camera.projection = projection;
+        camera
+    }
+
+    fn direction(camera: &Camera, x: f32, y: f32, width: usize, height: usize) -> Vec3 {
+        camera.primary_ray(x, y, width, height, (0.0, 0.0)).1
+    }
+
+    fn assert_direction(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected.normalize()).norm() < 1e-3, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn equirectangular_covers_every_direction() {
+        let camera = camera(Projection::Equirectangular);
+        let at = |x: f32, y: f32| direction(&camera, x, y, 200, 100);
+
+        assert_direction(at(100.0, 50.0), Vec3::new(0.0, 0.0, -1.0)); // Centro: hacia `center`
+        assert_direction(at(50.0, 50.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_direction(at(150.0, 50.0), Vec3::new(1.0, 0.0, 0.0));
+        // Los bordes izquierdo y derecho se juntan detrás de la cámara
+        assert_direction(at(0.0, 50.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_direction(at(200.0, 50.0), Vec3::new(0.0, 0.0, 1.0));
+        // Arriba y abajo, los polos
+        assert_direction(at(100.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_direction(at(100.0, 100.0), Vec3::new(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn cubemap_faces_look_along_each_axis() {
+        let camera = camera(Projection::Cubemap);
+        let at = |x: f32, y: f32| direction(&camera, x, y, 300, 200);
+
+        // Centro de cada cara, en el orden de la cuadrícula de 3x2
+        let faces = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        for (face, expected) in faces.iter().enumerate() {
+            let (column, row) = (face % 3, face / 3);
+            assert_direction(at(column as f32 * 100.0 + 50.0, row as f32 * 100.0 + 50.0), *expected);
+        }
+
+        // Las caras vecinas coinciden en sus bordes: el derecho del frente con el izquierdo de la
+        // derecha, y el de arriba del frente con el de abajo de la cara superior
+        assert_direction(at(200.0 - 1e-3, 150.0), Vec3::new(1.0, 0.0, -1.0));
+        assert_direction(at(0.0, 50.0), Vec3::new(1.0, 0.0, -1.0));
+        assert_direction(at(150.0, 100.0 + 1e-3), Vec3::new(0.0, 1.0, -1.0));
+        assert_direction(at(250.0, 100.0 - 1e-3), Vec3::new(0.0, 1.0, -1.0));
+    }
+
+    // Distancia de `point` a la recta del rayo que pasa por el centro de la imagen
+    fn distance_to_center_ray(camera: &Camera, point: &Vec3) -> f32 {
+        let (origin, direction) = camera.primary_ray(50.0, 50.0, 100, 100, (0.0, 0.0));
+        (point - origin).cross(&direction).norm()
+    }
+
+    #[test]
+    fn stereo_eyes_are_offset_and_converge() {
+        let stereo = Stereo::new(StereoMode::SideBySide, 0.5, 4.0);
+        let convergence_point = Vec3::new(0.0, 0.0, 1.0);
+
+        for projection in [Projection::Perspective, Projection::Orthographic] {
+            let camera = camera(projection);
+            let (left, right) = stereo.eyes(&camera);
+            // Medio `interocular` a cada lado, sobre el eje derecho de la cámara
+            assert!((left.eye - Vec3::new(-0.25, 0.0, 5.0)).norm() < 1e-6);
+            assert!((right.eye - Vec3::new(0.25, 0.0, 5.0)).norm() < 1e-6);
+
+            // El centro de las dos imágenes pasa por el punto a distancia `convergence`
+            assert!(distance_to_center_ray(&left, &convergence_point) < 1e-5, "{:?}", projection);
+            assert!(distance_to_center_ray(&right, &convergence_point) < 1e-5, "{:?}", projection);
+            // Fuera de ese plano hay paralaje: un punto más cercano se ve en sitios distintos
+            let (_, left_direction) = left.primary_ray(50.0, 50.0, 100, 100, (0.0, 0.0));
+            let (_, right_direction) = right.primary_ray(50.0, 50.0, 100, 100, (0.0, 0.0));
+            assert!((left_direction - right_direction).norm() > 0.1, "{:?}", projection);
+        }
+
+        // En perspectiva la imagen se descentra a distancia 1: medio ojo entre la convergencia
+        let (left, right) = stereo.eyes(&camera(Projection::Perspective));
+        assert!((left.shift_x - 0.0625).abs() < 1e-6 && (right.shift_x + 0.0625).abs() < 1e-6);
+
+        assert!(!Stereo::supports(Projection::Equirectangular));
+        assert!(!Stereo::supports(Projection::Cubemap));
+    }
+}
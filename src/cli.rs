@@ -0,0 +1,312 @@
+// cli.rs
+
+use nalgebra_glm::Vec3;
+use crate::camera::{ProjectionChoice, StereoMode};
+use crate::sampling::{PixelFilter, SamplePattern};
+use crate::tonemap::{ToneMapOperator, ToneMapping};
+
+// Más rayos por píxel no mejoran la imagen de forma visible y el render no terminaría nunca
+const MAX_SAMPLES: u32 = 65536;
+const MAX_FPS: u32 = 1000;
+
+pub const USAGE: &str = "\
+Usage: main [SCENE] [OPTIONS]
+
+Opens an interactive window, or renders a single image when --output is given.
+
+Arguments:
+  SCENE                 Scene file to load (default: scenes/diorama.toml)
+
+Options:
+  -o, --output FILE     Render once without a window and write the image to FILE
+                        (.bmp, .png or .ppm)
+      --width N         Image width in pixels (default: 800)
+      --height N        Image height in pixels (default: 800)
+      --eye X,Y,Z       Override the camera position
+      --center X,Y,Z    Override the point the camera looks at
+      --up X,Y,Z        Override the camera up vector
+      --fov DEGREES     Override the camera vertical field of view
+      --projection NAME Override the projection: perspective, orthographic, isometric,
+                        equirectangular (use a 2:1 image) or cubemap (use a 3:2 image)
+      --aperture R      Lens radius for depth of field, 0 keeps everything sharp
+      --focus-distance D
+                        Distance to the plane in focus (default: the camera center)
+  -s, --samples N       Rays per pixel, at most 65536; the regular pattern rounds it
+                        to a square grid (default: 1)
+      --pattern NAME    Pixel sample pattern: regular, stratified, halton or sobol
+                        (default: regular)
+      --filter NAME     Reconstruction filter: box, tent, gaussian or mitchell
+                        (default: box)
+      --seed N          Seed for the random sample patterns (default: 0)
+  -t, --threads N       Render threads, 0 uses every core (default: 0)
+      --tile-size N     Side in pixels of the square tiles each thread renders (default: 8)
+      --stereo MODE     Render both eyes: side-by-side or anaglyph (red/cyan)
+      --interocular D   Distance between the eyes in scene units (default: 0.25)
+      --convergence D   Distance where both eyes' images meet (default: the camera
+                        center)
+      --tonemap NAME    Tone mapping operator: clamp, reinhard or aces (default: clamp)
+      --exposure EV     Exposure in stops applied before tone mapping (default: 0)
+      --animate         Render every frame of the scene's camera animation to numbered
+                        files; '#' in the --output name marks the frame number
+                        (frame_####.png), otherwise it is appended (frame_0000.png).
+                        An --output ending in .y4m writes a single YUV4MPEG2 video instead
+      --fps N           Frames per second of the animation, 1 to 1000 (default: 24)
+      --missing-textures
+                        Replace textures that fail to load with a magenta/black checker
+                        and a warning instead of stopping
+  -v, --verbose         Print how many textures the scene loaded and their memory use
+  -h, --help            Print this help";
+
+pub struct Options {
+    pub scene_path: String,
+    pub output: Option<String>,
+    pub width: usize,
+    pub height: usize,
+    pub eye: Option<Vec3>,
+    pub center: Option<Vec3>,
+    pub up: Option<Vec3>,
+    pub fov: Option<f32>,
+    pub projection: Option<ProjectionChoice>,
+    pub aperture: Option<f32>,
+    pub focus_distance: Option<f32>,
+    pub samples: u32,
+    pub pattern: SamplePattern,
+    pub filter: PixelFilter,
+    pub seed: u64,
+    pub threads: usize,
+    pub tile_size: usize,
+    pub stereo: Option<StereoMode>,
+    pub interocular: f32,
+    pub convergence: Option<f32>,
+    pub tone_mapping: ToneMapping,
+    pub animate: bool,
+    pub fps: u32,
+    pub missing_textures: bool,
+    pub verbose: bool,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scene_path: "scenes/diorama.toml".to_string(),
+            output: None,
+            width: 800,
+            height: 800,
+            eye: None,
+            center: None,
+            up: None,
+            fov: None,
+            projection: None,
+            aperture: None,
+            focus_distance: None,
+            samples: 1,
+            pattern: SamplePattern::Regular,
+            filter: PixelFilter::Box,
+            seed: 0,
+            threads: 0,
+            tile_size: 8,
+            stereo: None,
+            interocular: 0.25,
+            convergence: None,
+            tone_mapping: ToneMapping::default(),
+            animate: false,
+            fps: 24,
+            missing_textures: false,
+            verbose: false,
+            help: false,
+        }
+    }
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut scene_path = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "-h" | "--help" => options.help = true,
+            "-o" | "--output" => options.output = Some(value(&arg)?),
+            "--width" => options.width = parse_positive(&arg, &value(&arg)?)?,
+            "--height" => options.height = parse_positive(&arg, &value(&arg)?)?,
+            "--eye" => options.eye = Some(parse_vec3(&arg, &value(&arg)?)?),
+            "--center" => options.center = Some(parse_vec3(&arg, &value(&arg)?)?),
+            "--up" => options.up = Some(parse_vec3(&arg, &value(&arg)?)?),
+            "--fov" => {
+                let text = value(&arg)?;
+                match text.parse::<f32>() {
+                    Ok(fov) if fov > 0.0 && fov < 180.0 => options.fov = Some(fov),
+                    _ => return Err(format!("{} expects an angle between 0 and 180, found '{}'", arg, text)),
+                }
+            }
+            "--projection" => {
+                let text = value(&arg)?;
+                options.projection = Some(
+                    ProjectionChoice::from_name(&text).ok_or_else(|| format!("unknown projection '{}'", text))?,
+                );
+            }
+            "--aperture" => {
+                let text = value(&arg)?;
+                match text.parse::<f32>() {
+                    Ok(aperture) if aperture >= 0.0 => options.aperture = Some(aperture),
+                    _ => return Err(format!("{} expects a non-negative number, found '{}'", arg, text)),
+                }
+            }
+            "--focus-distance" => {
+                let text = value(&arg)?;
+                match text.parse::<f32>() {
+                    Ok(distance) if distance > 0.0 => options.focus_distance = Some(distance),
+                    _ => return Err(format!("{} expects a positive number, found '{}'", arg, text)),
+                }
+            }
+            "-s" | "--samples" => options.samples = parse_count(&arg, &value(&arg)?, MAX_SAMPLES)?,
+            "--pattern" => {
+                let text = value(&arg)?;
+                options.pattern = SamplePattern::from_name(&text)
+                    .ok_or_else(|| format!("unknown sample pattern '{}'", text))?;
+            }
+            "--filter" => {
+                let text = value(&arg)?;
+                options.filter = PixelFilter::from_name(&text)
+                    .ok_or_else(|| format!("unknown reconstruction filter '{}'", text))?;
+            }
+            "--seed" => {
+                let text = value(&arg)?;
+                options.seed = text
+                    .parse()
+                    .map_err(|_| format!("invalid value '{}' for {}", text, arg))?;
+            }
+            "--animate" => options.animate = true,
+            "--fps" => options.fps = parse_count(&arg, &value(&arg)?, MAX_FPS)?,
+            "--missing-textures" => options.missing_textures = true,
+            "-v" | "--verbose" => options.verbose = true,
+            "-t" | "--threads" => {
+                let text = value(&arg)?;
+                options.threads = text
+                    .parse()
+                    .map_err(|_| format!("invalid value '{}' for {}", text, arg))?;
+            }
+            "--tile-size" => options.tile_size = parse_positive(&arg, &value(&arg)?)?,
+            "--stereo" => {
+                let text = value(&arg)?;
+                options.stereo = Some(
+                    StereoMode::from_name(&text).ok_or_else(|| format!("unknown stereo mode '{}'", text))?,
+                );
+            }
+            "--interocular" => {
+                let text = value(&arg)?;
+                match text.parse::<f32>() {
+                    Ok(distance) if distance >= 0.0 => options.interocular = distance,
+                    _ => return Err(format!("{} expects a non-negative number, found '{}'", arg, text)),
+                }
+            }
+            "--convergence" => {
+                let text = value(&arg)?;
+                match text.parse::<f32>() {
+                    Ok(distance) if distance > 0.0 => options.convergence = Some(distance),
+                    _ => return Err(format!("{} expects a positive number, found '{}'", arg, text)),
+                }
+            }
+            "--tonemap" => {
+                let text = value(&arg)?;
+                options.tone_mapping.operator = ToneMapOperator::from_name(&text)
+                    .ok_or_else(|| format!("unknown tone mapping operator '{}'", text))?;
+            }
+            "--exposure" => {
+                let text = value(&arg)?;
+                // NaN, infinito o 2^exposure desbordado dejarían toda la imagen en negro o sin valor
+                match text.parse::<f32>() {
+                    Ok(exposure) if (-64.0..=64.0).contains(&exposure) => options.tone_mapping.exposure = exposure,
+                    _ => return Err(format!("{} expects a number of stops between -64 and 64, found '{}'", arg, text)),
+                }
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if scene_path.is_some() {
+                    return Err(format!("unexpected argument '{}'", arg));
+                }
+                scene_path = Some(arg);
+            }
+        }
+    }
+
+    if let Some(path) = scene_path {
+        options.scene_path = path;
+    }
+
+    Ok(options)
+}
+
+fn parse_positive(name: &str, text: &str) -> Result<usize, String> {
+    match text.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{} expects a positive integer, found '{}'", name, text)),
+    }
+}
+
+// Entero entre 1 y `max`, sin truncar: "4294967296" es un error, no 0
+fn parse_count(name: &str, text: &str, max: u32) -> Result<u32, String> {
+    match text.parse::<u32>() {
+        Ok(n) if n > 0 && n <= max => Ok(n),
+        _ => Err(format!("{} expects an integer between 1 and {}, found '{}'", name, max, text)),
+    }
+}
+
+fn parse_vec3(name: &str, text: &str) -> Result<Vec3, String> {
+    let values: Vec<f32> = text
+        .split(',')
+        .map(|part| part.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("{} expects X,Y,Z, found '{}'", name, text))?;
+
+    if values.len() != 3 {
+        return Err(format!("{} expects X,Y,Z, found '{}'", name, text));
+    }
+
+    Ok(Vec3::new(values[0], values[1], values[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn tile_size_must_be_positive() {
+        assert_eq!(parse(&[]).unwrap().tile_size, 8);
+        assert_eq!(parse(&["--tile-size", "16"]).unwrap().tile_size, 16);
+        assert!(parse(&["--tile-size", "0"]).is_err());
+        assert!(parse(&["--tile-size", "-4"]).is_err());
+    }
+
+    #[test]
+    fn samples_are_bounded_instead_of_truncated() {
+        assert_eq!(parse(&["-s", "16"]).unwrap().samples, 16);
+        assert_eq!(parse(&["--samples", "65536"]).unwrap().samples, MAX_SAMPLES);
+        for text in ["0", "65537", "4294967296", "-1"] {
+            assert!(parse(&["--samples", text]).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn fps_is_bounded_instead_of_truncated() {
+        assert_eq!(parse(&[]).unwrap().fps, 24);
+        assert_eq!(parse(&["--fps", "60"]).unwrap().fps, 60);
+        for text in ["0", "1001", "4294967296", "-24"] {
+            assert!(parse(&["--fps", text]).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn exposure_must_be_finite() {
+        assert_eq!(parse(&["--exposure", "-1.5"]).unwrap().tone_mapping.exposure, -1.5);
+        for text in ["NaN", "inf", "-inf", "200", "bright"] {
+            assert!(parse(&["--exposure", text]).is_err(), "{}", text);
+        }
+    }
+}
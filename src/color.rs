@@ -1,23 +1,37 @@
 // color.rs
 
 use std::fmt;
+use std::sync::OnceLock;
 
-#[derive(Debug, Clone, Copy)]
+// Color RGB lineal en punto flotante. Los canales no se recortan al operar, así que pueden
+// superar 1.0 (luces intensas, sumas de varias luces); el recorte y la codificación sRGB sólo
+// se aplican al mostrar o exportar la imagen (ver `tonemap.rs` y `to_hex`).
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
-    r: u8,
-    g: u8,
-    b: u8,
+    r: f32,
+    g: f32,
+    b: f32,
 }
 
 impl Color {
+    // Color de 8 bits por canal en sRGB, como en un selector de color o una textura
     pub fn new(r: i32, g: i32, b: i32) -> Self {
         Color {
-            r: r.clamp(0, 255) as u8,
-            g: g.clamp(0, 255) as u8,
-            b: b.clamp(0, 255) as u8,
+            r: srgb_to_linear(r.clamp(0, 255) as u8),
+            g: srgb_to_linear(g.clamp(0, 255) as u8),
+            b: srgb_to_linear(b.clamp(0, 255) as u8),
         }
     }
 
+    // Color lineal; 1.0 equivale al blanco de pantalla
+    pub fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Color { r, g, b }
+    }
+
+    pub fn black() -> Self {
+        Color::rgb(0.0, 0.0, 0.0)
+    }
+
     pub fn from_hex(hex: u32) -> Color {
         let r = ((hex >> 16) & 0xFF) as u8;
         let g = ((hex >> 8) & 0xFF) as u8;
@@ -38,26 +52,66 @@ impl Color {
         Ok(Color::from_hex(hex))
     }
 
-    pub fn to_hex(&self) -> u32 {
-        ((self.r as u32) << 16) | ((self.g as u32) << 8) | (self.b as u32)
+    // Recorta a [0, 1] y codifica en sRGB de 8 bits para la pantalla o un archivo
+    pub fn to_hex(self) -> u32 {
+        let [r, g, b] = self.to_srgb8();
+        ((r as u32) << 16) | ((g as u32) << 8) | b as u32
     }
 
-    pub fn to_f32(&self) -> f32 {
-        let r = self.r as f32 / 255.0;
-        let g = self.g as f32 / 255.0;
-        let b = self.b as f32 / 255.0;
+    // Canales sRGB de 8 bits, recortados a [0, 1]; lo inverso de `Color::new`
+    pub fn to_srgb8(self) -> [u8; 3] {
+        [linear_to_srgb(self.r), linear_to_srgb(self.g), linear_to_srgb(self.b)]
+    }
+
+    pub fn r(&self) -> f32 {
+        self.r
+    }
 
-        (r + g + b)
+    pub fn g(&self) -> f32 {
+        self.g
+    }
+
+    pub fn b(&self) -> f32 {
+        self.b
+    }
+
+    // Aplica una función a cada canal
+    pub fn map<F: Fn(f32) -> f32>(self, f: F) -> Color {
+        Color::rgb(f(self.r), f(self.g), f(self.b))
     }
 
     pub fn lerp(&self, other: &Color, t: f32) -> Color {
-        let r = (self.r as f32 * (1.0 - t) + other.r as f32 * t) as u8;
-        let g = (self.g as f32 * (1.0 - t) + other.g as f32 * t) as u8;
-        let b = (self.b as f32 * (1.0 - t) + other.b as f32 * t) as u8;
-        Color::new(r.into(), g.into(), b.into())
+        *self * (1.0 - t) + *other * t
     }
 }
 
+// Sólo hay 256 valores posibles: se calculan una vez, porque las texturas decodifican cada
+// texel al muestrearlo
+fn srgb_to_linear(value: u8) -> f32 {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        std::array::from_fn(|value| {
+            let c = value as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    });
+    table[value as usize]
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let c = value.clamp(0.0, 1.0);
+    let encoded = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
 impl fmt::Display for Color {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Color(r: {}, g: {}, b: {})", self.r, self.g, self.b)
@@ -68,10 +122,7 @@ impl std::ops::Add for Color {
     type Output = Color;
 
     fn add(self, other: Color) -> Color {
-        let r = self.r.saturating_add(other.r);
-        let g = self.g.saturating_add(other.g);
-        let b = self.b.saturating_add(other.b);
-        Color::new(r.into(), g.into(), b.into())
+        Color::rgb(self.r + other.r, self.g + other.g, self.b + other.b)
     }
 }
 
@@ -79,10 +130,16 @@ impl std::ops::Mul<f32> for Color {
     type Output = Color;
 
     fn mul(self, factor: f32) -> Color {
-        let r = (self.r as f32 * factor).clamp(0.0, 255.0) as u8;
-        let g = (self.g as f32 * factor).clamp(0.0, 255.0) as u8;
-        let b = (self.b as f32 * factor).clamp(0.0, 255.0) as u8;
-        Color::new(r.into(), g.into(), b.into())
+        Color::rgb(self.r * factor, self.g * factor, self.b * factor)
+    }
+}
+
+// Modulación componente a componente, p. ej. el color de una textura iluminado por una luz de color
+impl std::ops::Mul<Color> for Color {
+    type Output = Color;
+
+    fn mul(self, other: Color) -> Color {
+        Color::rgb(self.r * other.r, self.g * other.g, self.b * other.b)
     }
 }
 
@@ -90,9 +147,10 @@ impl std::ops::Sub for Color {
     type Output = Color;
 
     fn sub(self, other: Color) -> Color {
-        let r = (self.r as i16 - other.r as i16).clamp(0, 255) as u8;
-        let g = (self.g as i16 - other.g as i16).clamp(0, 255) as u8;
-        let b = (self.b as i16 - other.b as i16).clamp(0, 255) as u8;
-        Color::new(r.into(), g.into(), b.into())
+        Color::rgb(
+            (self.r - other.r).max(0.0),
+            (self.g - other.g).max(0.0),
+            (self.b - other.b).max(0.0),
+        )
     }
-}
\ No newline at end of file
+}
@@ -29,44 +29,45 @@ impl Cube {
     }
 
     pub fn get_uv(&self, point: &Vec3, normal: &Vec3) -> (f32, f32) {
-        let mut u = 0.0;
-        let mut v = 0.0;
-
-        let min = self.min();
-        let max = self.max();
+        box_uv(&self.min(), &self.max(), point, normal)
+    }
+}
 
-        // Front face
-        if normal.z.abs() > 0.99 {
-            u = (point.x - min.x) / (max.x - min.x);
-            v = (point.y - min.y) / (max.y - min.y);
-        }
-        // Back face
-        else if normal.z.abs() > 0.01 && normal.z < 0.0 {
-            u = (max.x - point.x) / (max.x - min.x);
-            v = (point.y - min.y) / (max.y - min.y);
-        }
-        // Left face
-        else if normal.x.abs() > 0.99 && normal.x < 0.0 {
-            u = (max.z - point.z) / (max.z - min.z);
-            v = (point.y - min.y) / (max.y - min.y);
-        }
-        // Right face
-        else if normal.x.abs() > 0.99 && normal.x > 0.0 {
-            u = (point.z - min.z) / (max.z - min.z);
-            v = (point.y - min.y) / (max.y - min.y);
-        }
-        // Top face
-        else if normal.y.abs() > 0.99 && normal.y > 0.0 {
-            u = (point.x - min.x) / (max.x - min.x);
-            v = (max.z - point.z) / (max.z - min.z);
-        }
-        // Bottom face
-        else if normal.y.abs() > 0.99 && normal.y < 0.0 {
-            u = (point.x - min.x) / (max.x - min.x);
-            v = (point.z - min.z) / (max.z - min.z);
-        }
+// Coordenadas UV de un punto sobre la cara de una caja alineada a los ejes
+pub fn box_uv(min: &Vec3, max: &Vec3, point: &Vec3, normal: &Vec3) -> (f32, f32) {
+    let mut u = 0.0;
+    let mut v = 0.0;
 
-        (u, v)
+    // Front face
+    if normal.z.abs() > 0.99 {
+        u = (point.x - min.x) / (max.x - min.x);
+        v = (point.y - min.y) / (max.y - min.y);
+    }
+    // Back face
+    else if normal.z.abs() > 0.01 && normal.z < 0.0 {
+        u = (max.x - point.x) / (max.x - min.x);
+        v = (point.y - min.y) / (max.y - min.y);
+    }
+    // Left face
+    else if normal.x.abs() > 0.99 && normal.x < 0.0 {
+        u = (max.z - point.z) / (max.z - min.z);
+        v = (point.y - min.y) / (max.y - min.y);
+    }
+    // Right face
+    else if normal.x.abs() > 0.99 && normal.x > 0.0 {
+        u = (point.z - min.z) / (max.z - min.z);
+        v = (point.y - min.y) / (max.y - min.y);
+    }
+    // Top face
+    else if normal.y.abs() > 0.99 && normal.y > 0.0 {
+        u = (point.x - min.x) / (max.x - min.x);
+        v = (max.z - point.z) / (max.z - min.z);
+    }
+    // Bottom face
+    else if normal.y.abs() > 0.99 && normal.y < 0.0 {
+        u = (point.x - min.x) / (max.x - min.x);
+        v = (point.z - min.z) / (max.z - min.z);
     }
-}
 
+    (u, v)
+}
@@ -1,15 +1,67 @@
 // framebuffer.rs
 
 use crate::bmp::write_bmp_file;
+use crate::ppm::write_ppm_file;
 use crate::color::Color;
 use crate::camera::Camera;
+use crate::tonemap::ToneMapping;
 
 use std::f32::consts::PI;
+use std::fmt;
+use std::path::Path;
 
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    UnsupportedFormat(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::Io(err) => write!(f, "{}", err),
+            ExportError::Image(err) => write!(f, "{}", err),
+            ExportError::UnsupportedFormat(extension) => write!(
+                f,
+                "unsupported image format '{}', expected .bmp, .png or .ppm",
+                extension
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::Io(err) => Some(err),
+            ExportError::Image(err) => Some(err),
+            ExportError::UnsupportedFormat(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(err: std::io::Error) -> Self {
+        ExportError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for ExportError {
+    fn from(err: image::ImageError) -> Self {
+        ExportError::Image(err)
+    }
+}
+
+// El dibujo se acumula en `hdr` como color lineal sin recortar; `resolve` aplica el mapeo de
+// tonos y la codificación sRGB sobre `buffer`, que es lo que se muestra o se exporta.
 pub struct Framebuffer {
     width: usize,
     height: usize,
     buffer: Vec<u32>,
+    hdr: Vec<Color>,
+    accumulation: Vec<Color>, // Suma de las pasadas progresivas
+    accumulated_passes: u32,
     background_color: Color,
     current_color: Color,
 }
@@ -20,11 +72,15 @@ impl Framebuffer {
         let current_color = Color::new(255, 255, 255);
 
         let buffer = vec![background_color.to_hex(); width * height];
+        let hdr = vec![background_color; width * height];
 
         Framebuffer {
             width,
             height,
             buffer,
+            hdr,
+            accumulation: vec![Color::black(); width * height],
+            accumulated_passes: 0,
             background_color,
             current_color,
         }
@@ -42,15 +98,73 @@ impl Framebuffer {
         &self.buffer
     }
 
+    pub fn get_hdr_buffer(&self) -> &[Color] {
+        &self.hdr
+    }
+
+    pub fn get_hdr_buffer_mut(&mut self) -> &mut [Color] {
+        &mut self.hdr
+    }
+
     pub fn clear(&mut self) {
-        let color_hex = self.background_color.to_hex();
-        self.buffer.fill(color_hex);
+        self.hdr.fill(self.background_color);
     }
 
     pub fn point(&mut self, x: isize, y: isize) {
         if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
             let index = (self.width * y as usize) + x as usize;
-            self.buffer[index] = self.current_color.to_hex();
+            self.hdr[index] = self.current_color;
+        }
+    }
+
+    // Suma la imagen actual a la acumulación y deja en el buffer HDR el promedio de todas las
+    // pasadas desde el último `reset_accumulation`
+    pub fn accumulate(&mut self) {
+        self.accumulated_passes += 1;
+        let scale = 1.0 / self.accumulated_passes as f32;
+        for (sum, color) in self.accumulation.iter_mut().zip(self.hdr.iter_mut()) {
+            *sum = *sum + *color;
+            *color = *sum * scale;
+        }
+    }
+
+    pub fn reset_accumulation(&mut self) {
+        self.accumulation.fill(Color::black());
+        self.accumulated_passes = 0;
+    }
+
+    pub fn accumulated_passes(&self) -> u32 {
+        self.accumulated_passes
+    }
+
+    // Copia `source`, de menor resolución, estirándola al tamaño de este framebuffer sin
+    // interpolar. Se usa para la vista previa mientras la cámara se mueve.
+    pub fn upscale_from(&mut self, source: &Framebuffer) {
+        for y in 0..self.height {
+            let source_y = (y * source.height / self.height).min(source.height - 1);
+            for x in 0..self.width {
+                let source_x = (x * source.width / self.width).min(source.width - 1);
+                self.hdr[y * self.width + x] = source.hdr[source_y * source.width + source_x];
+            }
+        }
+    }
+
+    // Copia `source` en este framebuffer con su esquina superior izquierda en `(x, y)`,
+    // recortando lo que quede fuera
+    pub fn blit(&mut self, source: &Framebuffer, x: usize, y: usize) {
+        let columns = source.width.min(self.width.saturating_sub(x));
+        for row in 0..source.height.min(self.height.saturating_sub(y)) {
+            let from = row * source.width;
+            let to = (y + row) * self.width + x;
+            self.hdr[to..to + columns].copy_from_slice(&source.hdr[from..from + columns]);
+        }
+    }
+
+    // Convierte el buffer HDR en píxeles de pantalla. Hay que llamarlo antes de mostrar o
+    // guardar la imagen.
+    pub fn resolve(&mut self, tone_mapping: &ToneMapping) {
+        for (pixel, color) in self.buffer.iter_mut().zip(&self.hdr) {
+            *pixel = tone_mapping.apply(*color).to_hex();
         }
     }
 
@@ -71,11 +185,48 @@ impl Framebuffer {
         }
     }
 
+    // Elige el formato según la extensión del archivo
+    pub fn save(&self, file_path: &str) -> Result<(), ExportError> {
+        let extension = Path::new(file_path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+
+        match extension.as_str() {
+            "bmp" => Ok(self.save_as_bmp(file_path)?),
+            "png" => self.save_as_png(file_path),
+            "ppm" => Ok(self.save_as_ppm(file_path)?),
+            _ => Err(ExportError::UnsupportedFormat(extension)),
+        }
+    }
+
     pub fn save_as_bmp(&self, file_path: &str) -> std::io::Result<()> {
         write_bmp_file(file_path, &self.buffer, self.width, self.height)
     }
 
-    pub fn fill_polygon(&mut self, vertices: &Vec<[isize; 2]>, fill_color: Color) {
+    pub fn save_as_ppm(&self, file_path: &str) -> std::io::Result<()> {
+        write_ppm_file(file_path, &self.buffer, self.width, self.height)
+    }
+
+    pub fn save_as_png(&self, file_path: &str) -> Result<(), ExportError> {
+        let mut rgb = Vec::with_capacity(self.width * self.height * 3);
+        for pixel in &self.buffer {
+            rgb.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, *pixel as u8]);
+        }
+
+        image::save_buffer_with_format(
+            file_path,
+            &rgb,
+            self.width as u32,
+            self.height as u32,
+            image::ColorType::Rgb8,
+            image::ImageFormat::Png,
+        )?;
+        Ok(())
+    }
+
+    pub fn fill_polygon(&mut self, vertices: &[[isize; 2]], fill_color: Color) {
         let min_y = vertices.iter().map(|v| v[1]).min().unwrap_or(0);
         let max_y = vertices.iter().map(|v| v[1]).max().unwrap_or(0);
 
@@ -108,7 +259,6 @@ impl Framebuffer {
         let ground_color = Color::new(0, 0, 128);
 
         let pitch = camera.get_pitch();
-        let yaw = camera.get_yaw();
 
         let horizon_height = ((self.height as f32 / 2.0) * (1.0 + pitch / (PI / 2.0))) as isize;
 
@@ -132,3 +282,81 @@ impl Framebuffer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    // Imagen de 3x2: con 9 bytes por fila, el BMP necesita 3 bytes de relleno
+    fn framebuffer() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.buffer = vec![0x010203, 0x040506, 0x070809, 0x0a0b0c, 0x0d0e0f, 0x101112];
+        framebuffer
+    }
+
+    fn export(extension: &str) -> Vec<u8> {
+        let dir = std::env::temp_dir().join(format!("framebuffer-{}-{}", extension, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("image.{}", extension));
+        framebuffer().save(path.to_str().unwrap()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn ppm_has_a_text_header_and_rows_from_the_top() {
+        let bytes = export("ppm");
+        let mut expected = b"P6\n3 2\n255\n".to_vec();
+        expected.extend(1..=18u8);
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn bmp_rows_are_padded_and_stored_bottom_up() {
+        let bytes = export("bmp");
+        assert_eq!(bytes.len(), 54 + 2 * 12);
+
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u16_at = |offset: usize| u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap());
+        assert_eq!(&bytes[0..2], b"BM");
+        assert_eq!(u32_at(2), 78); // Tamaño del archivo
+        assert_eq!(u32_at(10), 54); // Inicio de los píxeles
+        assert_eq!(u32_at(14), 40);
+        assert_eq!((u32_at(18), u32_at(22)), (3, 2));
+        assert_eq!((u16_at(26), u16_at(28)), (1, 24));
+        assert_eq!(u32_at(34), 24); // Tamaño de los píxeles con el relleno
+
+        // Primero la fila de abajo, en orden BGR y rellenada hasta 12 bytes
+        assert_eq!(
+            &bytes[54..],
+            &[
+                0x0c, 0x0b, 0x0a, 0x0f, 0x0e, 0x0d, 0x12, 0x11, 0x10, 0, 0, 0,
+                0x03, 0x02, 0x01, 0x06, 0x05, 0x04, 0x09, 0x08, 0x07, 0, 0, 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn png_round_trips() {
+        let bytes = export("png");
+        let image = image::load_from_memory(&bytes).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.into_raw(), (1..=18u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn export_errors_keep_their_cause() {
+        let err = framebuffer().save("image.gif").unwrap_err();
+        assert!(matches!(err, ExportError::UnsupportedFormat(ref extension) if extension == "gif"));
+        assert!(err.source().is_none());
+
+        let missing_dir = std::env::temp_dir().join(format!("framebuffer-missing-{}", std::process::id()));
+        for extension in ["bmp", "ppm", "png"] {
+            let path = missing_dir.join(format!("image.{}", extension));
+            let err = framebuffer().save(path.to_str().unwrap()).unwrap_err();
+            assert!(err.source().is_some(), "{}: {}", extension, err);
+        }
+    }
+}
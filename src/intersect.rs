@@ -13,6 +13,7 @@ pub struct Intersect {
     pub material: Option<Arc<Material>>, // Material en el punto de intersección
     pub u: f32,             // Coordenada U para texturizado
     pub v: f32,             // Coordenada V para texturizado
+    pub uv_scale: f32,      // Unidades de UV por unidad de distancia sobre la superficie
 }
 
 impl Intersect {
@@ -26,9 +27,15 @@ impl Intersect {
             material,
             u,
             v,
+            uv_scale: 1.0,
         }
     }
 
+    // Indica si el punto cae en una zona recortada por el alfa de la textura (ver `AlphaMode::Mask`)
+    pub fn is_cut_out(&self) -> bool {
+        self.material.as_ref().is_some_and(|material| material.is_cut_out(&self.normal, self.u, self.v))
+    }
+
     // Método para crear un Intersect vacío
     pub fn empty() -> Self {
         Intersect {
@@ -39,6 +46,7 @@ impl Intersect {
             material: None, // No hay material para un Intersect vacío
             u: 0.0,
             v: 0.0,
+            uv_scale: 0.0,
         }
     }
 }
@@ -1,18 +1,151 @@
 use nalgebra_glm::Vec3;
 use crate::color::Color;
 
+#[derive(Debug, Clone)]
+pub enum LightKind {
+    // Luz puntual que ilumina en todas direcciones
+    Point { position: Vec3 },
+    // Luz muy lejana (el sol): sólo tiene dirección, hacia donde viaja la luz
+    Directional { direction: Vec3 },
+    // Foco: luz puntual limitada a un cono. Los ángulos son medios ángulos en radianes;
+    // entre `inner_angle` y `outer_angle` la intensidad decae con exponente `falloff`.
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+        falloff: f32,
+    },
+    // Rectángulo emisor centrado en `position`, con lados `edge_u` y `edge_v`
+    Rect { position: Vec3, edge_u: Vec3, edge_v: Vec3 },
+    // Esfera emisora
+    Sphere { position: Vec3, radius: f32 },
+}
+
+#[derive(Debug, Clone)]
 pub struct Light {
-    pub position: Vec3,
+    pub kind: LightKind,
     pub color: Color,
     pub intensity: f32,
+    pub samples: u32, // Rayos de sombra por punto; sólo las luces de área usan más de uno
 }
 
 impl Light {
     pub fn new(position: Vec3, color: Color, intensity: f32) -> Self {
         Light {
-            position,
+            kind: LightKind::Point { position },
+            color,
+            intensity,
+            samples: 1,
+        }
+    }
+
+    pub fn directional(direction: Vec3, color: Color, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Directional { direction: direction.normalize() },
+            color,
+            intensity,
+            samples: 1,
+        }
+    }
+
+    pub fn spot(
+        position: Vec3,
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+        falloff: f32,
+        color: Color,
+        intensity: f32,
+    ) -> Self {
+        Light {
+            kind: LightKind::Spot {
+                position,
+                direction: direction.normalize(),
+                inner_angle: inner_angle.min(outer_angle),
+                outer_angle,
+                falloff,
+            },
+            color,
+            intensity,
+            samples: 1,
+        }
+    }
+
+    pub fn rect(position: Vec3, edge_u: Vec3, edge_v: Vec3, color: Color, intensity: f32, samples: u32) -> Self {
+        Light {
+            kind: LightKind::Rect { position, edge_u, edge_v },
+            color,
+            intensity,
+            samples: samples.max(1),
+        }
+    }
+
+    pub fn sphere(position: Vec3, radius: f32, color: Color, intensity: f32, samples: u32) -> Self {
+        Light {
+            kind: LightKind::Sphere { position, radius },
             color,
             intensity,
+            samples: samples.max(1),
         }
     }
-}
\ No newline at end of file
+
+    // Punto de la luz que corresponde a la muestra `(u, v)` en [0, 1)^2, visto desde `point`.
+    // Devuelve `None` para las luces direccionales, que no tienen posición.
+    pub fn sample_position(&self, point: &Vec3, u: f32, v: f32) -> Option<Vec3> {
+        match &self.kind {
+            LightKind::Point { position } | LightKind::Spot { position, .. } => Some(*position),
+            LightKind::Directional { .. } => None,
+            LightKind::Rect { position, edge_u, edge_v } => {
+                Some(position + edge_u * (u - 0.5) + edge_v * (v - 0.5))
+            }
+            LightKind::Sphere { position, radius } => {
+                // Muestrear el disco de la esfera que se ve desde `point`
+                let axis = (point - position).normalize();
+                let helper = if axis.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+                let tangent = axis.cross(&helper).normalize();
+                let bitangent = axis.cross(&tangent);
+
+                let r = radius * u.sqrt();
+                let theta = 2.0 * std::f32::consts::PI * v;
+                Some(position + tangent * (r * theta.cos()) + bitangent * (r * theta.sin()))
+            }
+        }
+    }
+
+    // Dirección normalizada desde `point` hacia la luz y distancia hasta ella
+    pub fn direction_from(&self, point: &Vec3) -> (Vec3, f32) {
+        match &self.kind {
+            LightKind::Point { position }
+            | LightKind::Spot { position, .. }
+            | LightKind::Rect { position, .. }
+            | LightKind::Sphere { position, .. } => {
+                let to_light = position - point;
+                let distance = to_light.magnitude();
+                (to_light / distance, distance)
+            }
+            LightKind::Directional { direction } => (-direction, f32::INFINITY),
+        }
+    }
+
+    // Fracción de la intensidad que llega a `point` por la forma de la luz (el cono de un foco)
+    pub fn attenuation(&self, point: &Vec3) -> f32 {
+        match &self.kind {
+            LightKind::Spot { position, direction, inner_angle, outer_angle, falloff } => {
+                let cos_angle = (point - position).normalize().dot(direction);
+                let cos_inner = inner_angle.cos();
+                let cos_outer = outer_angle.cos();
+
+                if cos_angle >= cos_inner {
+                    1.0
+                } else if cos_angle <= cos_outer {
+                    0.0
+                } else {
+                    let t = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+                    t.powf(*falloff)
+                }
+            }
+            _ => 1.0,
+        }
+    }
+}
@@ -5,290 +5,211 @@ mod material;
 mod ray_intersect;
 mod render;
 mod bmp;
+mod ppm;
 mod camera;
 mod light;
 mod figures;
 mod texture;
+mod bvh;
+mod voxel;
+mod scene;
+mod cli;
+mod sampling;
+mod animation;
+mod y4m;
+mod tonemap;
 
-use framebuffer::Framebuffer;
+use framebuffer::{ExportError, Framebuffer};
 use color::Color;
 use material::Material;
-use figures::Sphere;
-use figures::Cube;
-use render::render;
+use render::{autofocus, render, render_stereo, RenderSettings};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use bvh::Bvh;
+use scene::load_scene;
+use texture::TextureCache;
+use camera::{Camera, FovAxis, Projection, Stereo};
 use light::Light;
-use nalgebra_glm::{Vec3, vec3};
-use minifb::{Key, Window, WindowOptions};
-use camera::Camera;
+use y4m::Y4mWriter;
+use sampling::{PixelFilter, SamplePattern};
 
-use std::sync::Arc;
-use crate::ray_intersect::Renderable;
-use crate::texture::Texture;
+use std::process;
+use std::time::Instant;
 
-fn main() {
+// Divisor de resolución de la vista previa mientras la cámara se mueve
+const PREVIEW_SCALE: usize = 4;
+// Pasadas que se acumulan con la cámara quieta antes de dejar de renderizar
+const MAX_PROGRESSIVE_PASSES: u32 = 64;
+// Velocidad del modo de vuelo en unidades por segundo
+const FLY_SPEED: f32 = 4.0;
+// Radianes que gira la vista por cada píxel que se mueve el ratón
+const MOUSE_SENSITIVITY: f32 = 0.005;
 
-    let dirt_grass = Material {
-        diffuse: Color::from_hex(0x8B4513),
-        specular: 0.05,  // Bajo brillo
-        albedo: [0.9, 0.1, 0.0, 0.0],
-        refractive_index: 1.0,
-        textures: [
-            Some(Texture::load_from_file("textures/dirt_side.webp")), //Right
-            Some(Texture::load_from_file("textures/dirt_side.webp")), //Left
-            Some(Texture::load_from_file("textures/dirt_top.jpg")), //Top
-            Some(Texture::load_from_file("textures/dirt.png")), //Bottom
-            Some(Texture::load_from_file("textures/dirt_side.webp")), //Front
-            Some(Texture::load_from_file("textures/dirt_side.webp")), //Back
-        ],
-        has_texture: true,
-    };
+// Cómo responde la cámara al teclado y al ratón
+#[derive(Clone, Copy, PartialEq)]
+enum CameraMode {
+    // WASD orbita alrededor de `center` y las flechas acercan o alejan
+    Orbit,
+    // WASD camina, E/Q sube y baja, y arrastrar el ratón gira la vista
+    Fly,
+}
 
-    let dirt = Material {
-        diffuse: Color::from_hex(0x8B4513),
-        specular: 0.05,  // Bajo brillo
-        albedo: [0.9, 0.1, 0.0, 0.0],
-        refractive_index: 1.0,
-        textures: [
-            Some(Texture::load_from_file("textures/dirt.png")),
-            Some(Texture::load_from_file("textures/dirt.png")),
-            Some(Texture::load_from_file("textures/dirt.png")),
-            Some(Texture::load_from_file("textures/dirt.png")), 
-            Some(Texture::load_from_file("textures/dirt.png")), 
-            Some(Texture::load_from_file("textures/dirt.png")), 
-        ],
-        has_texture: true,
+fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            process::exit(2);
+        }
     };
 
-    let cobblestone = Material {
-        diffuse: Color::from_hex(0x696969),
-        specular: 0.3,
-        albedo: [0.7, 0.3, 0.0, 0.0],
-        refractive_index: 1.0,
-        textures: [
-            Some(Texture::load_from_file("textures/cobblestone.png")),
-            Some(Texture::load_from_file("textures/cobblestone.png")),
-            Some(Texture::load_from_file("textures/cobblestone.png")),
-            Some(Texture::load_from_file("textures/cobblestone.png")),
-            Some(Texture::load_from_file("textures/cobblestone.png")),
-            Some(Texture::load_from_file("textures/cobblestone.png")),
-        ],
-        has_texture: true,
-    };
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
 
-    let leaf = Material {
-        diffuse: Color::from_hex(0x228B22),
-        specular: 0.2,
-        albedo: [0.8, 0.2, 0.1, 0.0],
-        refractive_index: 1.0,
-        textures: [
-            Some(Texture::load_from_file("textures/leaf.webp")),
-            Some(Texture::load_from_file("textures/leaf.webp")),
-            Some(Texture::load_from_file("textures/leaf.webp")),
-            Some(Texture::load_from_file("textures/leaf.webp")),
-            Some(Texture::load_from_file("textures/leaf.webp")),
-            Some(Texture::load_from_file("textures/leaf.webp")),
-        ],
-        has_texture: true,
+    let mut textures = TextureCache::new();
+    textures.substitute_missing = options.missing_textures;
+    let scene = match load_scene(&options.scene_path, textures) {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("{}: {}", options.scene_path, err);
+            process::exit(1);
+        }
     };
+    if options.verbose && !scene.textures.is_empty() {
+        eprintln!(
+            "{}: {} textures, {:.1} MiB",
+            options.scene_path,
+            scene.textures.len(),
+            scene.textures.memory_usage() as f32 / (1024.0 * 1024.0)
+        );
+    }
 
-    let lava = Material {
-        diffuse: Color::from_hex(0xFF4500),
-        specular: 0.6,
-        albedo: [0.8, 0.6, 0.0, 0.0],
-        refractive_index: 1.3,
-        textures: [
-            Some(Texture::load_from_file("textures/lava.gif")),
-            Some(Texture::load_from_file("textures/lava.gif")),
-            Some(Texture::load_from_file("textures/lava.gif")),
-            Some(Texture::load_from_file("textures/lava.gif")),
-            Some(Texture::load_from_file("textures/lava.gif")),
-            Some(Texture::load_from_file("textures/lava.gif")),
-        ],
-        has_texture: true,
-    };
+    let width = options.width;
+    let height = options.height;
+    let mut framebuffer = Framebuffer::new(width, height);
 
-    let oak_log = Material {
-        diffuse: Color::from_hex(0x8B4513),
-        specular: 0.1,
-        albedo: [0.9, 0.1, 0.0, 0.0],
-        refractive_index: 1.0,
-        textures: [
-            Some(Texture::load_from_file("textures/log_oak.png")),
-            Some(Texture::load_from_file("textures/log_oak.png")),
-            Some(Texture::load_from_file("textures/log_oak.png")),
-            Some(Texture::load_from_file("textures/log_oak.png")),
-            Some(Texture::load_from_file("textures/log_oak.png")),
-            Some(Texture::load_from_file("textures/log_oak.png")),
-        ],
-        has_texture: true,
-    };
+    let mut camera = scene.camera;
+    if let Some(eye) = options.eye {
+        camera.eye = eye;
+    }
+    if let Some(center) = options.center {
+        camera.center = center;
+    }
+    if let Some(up) = options.up {
+        camera.up = up;
+    }
+    if let Some(fov) = options.fov {
+        camera.fov = fov.to_radians();
+        camera.fov_axis = FovAxis::Vertical;
+    }
+    if options.eye.is_some() || options.center.is_some() {
+        camera.focus_distance = (camera.eye - camera.center).magnitude();
+    }
+    if let Some(aperture) = options.aperture {
+        camera.aperture = aperture;
+    }
+    if let Some(focus_distance) = options.focus_distance {
+        camera.focus_distance = focus_distance;
+    }
+    let aspect_ratio = width as f32 / height as f32;
+    if let Some(choice) = options.projection {
+        camera.apply_projection(choice, aspect_ratio);
+    }
 
-    let water = Material {
-        diffuse: Color::from_hex(0x1E90FF),
-        specular: 0.9,
-        albedo: [0.2, 0.7, 0.1, 0.0],
-        refractive_index: 1.33,
-        textures: [
-            Some(Texture::load_from_file("textures/water.webp")),
-            Some(Texture::load_from_file("textures/water.webp")),
-            Some(Texture::load_from_file("textures/water.webp")),
-            Some(Texture::load_from_file("textures/water.webp")),
-            Some(Texture::load_from_file("textures/water.webp")),
-            Some(Texture::load_from_file("textures/water.webp")),
-        ],
-        has_texture: true,
-    };
+    // Sin `--convergence`, las imágenes de los dos ojos coinciden en `center`
+    let stereo = options.stereo.map(|mode| {
+        let convergence = options.convergence.unwrap_or_else(|| (camera.eye - camera.center).magnitude());
+        Stereo::new(mode, options.interocular, convergence)
+    });
+    if stereo.is_some() && !Stereo::supports(camera.projection) {
+        eprintln!("error: --stereo needs a perspective or orthographic projection");
+        process::exit(1);
+    }
+
+    // Construir la jerarquía de volúmenes una sola vez; la escena es estática
+    let objects = Bvh::new(scene.objects);
 
-    let chest_tetxure = Material {
-        diffuse: Color::from_hex(0x8B4513),
-        specular: 0.3,
-        albedo: [0.7, 0.2, 0.1, 0.0],
-        refractive_index: 1.0,
-        textures: [
-            Some(Texture::load_from_file("textures/chest_side.png")), //Right
-            Some(Texture::load_from_file("textures/chest_front.png")), //Left
-            Some(Texture::load_from_file("textures/chest_top.png")), //Top
-            Some(Texture::load_from_file("textures/chest_top.png")), //Bottom
-            Some(Texture::load_from_file("textures/chest_side.png")), //Front
-            Some(Texture::load_from_file("textures/chest_side.png")), //Back
-        ],
-        has_texture: true
+    let mut render_settings = match RenderSettings::new(options.tile_size, options.threads) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("error: cannot start {} render threads: {}", options.threads, err);
+            process::exit(1);
+        }
     };
+    render_settings.samples = options.samples;
+    render_settings.pattern = options.pattern;
+    render_settings.filter = options.filter;
+    render_settings.seed = options.seed;
 
-    let width = 800;  // Ajusta el tamaño del framebuffer según sea necesario
-    let height = 800; // Ajusta el tamaño del framebuffer según sea necesario
-    let mut framebuffer = Framebuffer::new(width, height);
+    // Animación sin ventana: un archivo numerado por cuadro
+    if options.animate {
+        let Some(animation) = &scene.animation else {
+            eprintln!("{}: the scene has no [[keyframe]] to animate", options.scene_path);
+            process::exit(1);
+        };
+        let Some(output) = &options.output else {
+            eprintln!("error: --animate needs --output\n\n{}", cli::USAGE);
+            process::exit(2);
+        };
 
+        // Con salida .y4m todos los cuadros van a un único flujo de vídeo
+        let is_video = output.to_ascii_lowercase().ends_with(".y4m");
+        let mut video = if is_video {
+            match Y4mWriter::create(output, width, height, options.fps) {
+                Ok(writer) => Some(writer),
+                Err(err) => {
+                    eprintln!("{}: {}", output, err);
+                    process::exit(1);
+                }
+            }
+        } else {
+            None
+        };
 
-    let mut objects: Vec<Box<dyn Renderable>> = Vec::new(); 
+        let frame_count = ((animation.end() - animation.start()) * options.fps as f32).round() as usize + 1;
+        for frame in 0..frame_count {
+            let time = animation.start() + frame as f32 / options.fps as f32;
+            let frame_camera = animation.camera_at(&camera, time);
 
-    for x in 0..=5 {
-        for z in 0..=2 {
-            let invalid_positions = vec![(1, 1), (2, 1), (3, 1), (1, 2)];
-    
-            if !invalid_positions.contains(&(x, z)) {
-                let cube = Cube {
-                    center: Vec3::new(x as f32, 0.0, z as f32),
-                    length: 1,
-                    material: dirt_grass.clone().into(),
-                };
-                objects.push(Box::new(cube));
-            }
-        }
-    }
-    
-
-    for x in 0..=2 {
-        for z in 3..=5 {
-            let cube = Cube {
-                center: Vec3::new(x as f32, 0.0, z as f32),
-                length: 1,
-                material: dirt_grass.clone().into(),
-            };
-            objects.push(Box::new(cube));
-        }
-    }
+            draw_frame(&mut framebuffer, &objects, &frame_camera, &scene.lights, &render_settings, stereo.as_ref());
+            framebuffer.resolve(&options.tone_mapping);
 
-    for x in 0..=5 {
-        for z in 0..=2 {
-            let cube = Cube {
-                center: Vec3::new(x as f32, -1.0, z as f32),
-                length: 1,
-                material: dirt.clone().into(),
+            let (path, result) = match &mut video {
+                Some(writer) => (output.clone(), writer.write_frame(framebuffer.get_buffer()).map_err(ExportError::from)),
+                None => {
+                    let path = frame_path(output, frame);
+                    let result = framebuffer.save(&path);
+                    (path, result)
+                }
             };
-            objects.push(Box::new(cube));
+            if let Err(err) = result {
+                eprintln!("{}: {}", path, err);
+                process::exit(1);
+            }
+            eprintln!("frame {}/{}: {}", frame + 1, frame_count, path);
         }
-    }
-    
-
-    for x in 0..=2 {
-        for z in 3..=5 {
-            let cube = Cube {
-                center: Vec3::new(x as f32, -1.0, z as f32),
-                length: 1,
-                material: dirt.clone().into(),
-            };
-            objects.push(Box::new(cube));
+
+        if let Some(writer) = video {
+            if let Err(err) = writer.finish() {
+                eprintln!("{}: {}", output, err);
+                process::exit(1);
+            }
         }
+        return;
     }
 
-    for i in 1..=3 {
-        objects.push(Box::new(Cube {
-            center: Vec3::new(1.0, i as f32, 4.0), 
-            length: 1,
-            material: oak_log.clone().into(), 
-        }));
-    }
+    // Modo sin ventana: renderizar una sola imagen y escribirla a disco
+    if let Some(output) = &options.output {
+        draw_frame(&mut framebuffer, &objects, &camera, &scene.lights, &render_settings, stereo.as_ref());
+        framebuffer.resolve(&options.tone_mapping);
 
-    for x in -1..=3 {
-        for z in 2..=6 {
-            objects.push(Box::new(Cube {
-                center: Vec3::new(x as f32, 3.0, z as f32),
-                length: 1,
-                material: leaf.clone().into(),
-            }));
-    
-            objects.push(Box::new(Cube {
-                center: Vec3::new(x as f32, 4.0, z as f32),
-                length: 1,
-                material: leaf.clone().into(),
-            }));
+        if let Err(err) = framebuffer.save(output) {
+            eprintln!("{}: {}", output, err);
+            process::exit(1);
         }
+        return;
     }
-    
-    for (x, z) in &[
-        (0.0, 3.0), (1.0, 3.0), (2.0, 3.0),
-        (0.0, 5.0), (1.0, 5.0), (2.0, 5.0),
-        (0.0, 4.0), (1.0, 4.0), (2.0, 4.0),
-    ] {
-        objects.push(Box::new(Cube {
-            center: Vec3::new(*x, 5.0, *z),
-            length: 1,
-            material: leaf.clone().into(),
-        }));
-    }
-
-    objects.push(Box::new(Cube {
-        center: Vec3::new(1.0, 0.0, 2.0),
-        length: 1,
-        material: lava.clone().into(),
-    }));
-
-    objects.push(Box::new(Cube {
-        center: Vec3::new(3.0, 0.0, 1.0),
-        length: 1,
-        material: water.clone().into(),
-    }));
-
-    objects.push(Box::new(Cube {
-        center: Vec3::new(2.0, 0.0, 1.0),
-        length: 1,
-        material: water.clone().into(),
-    }));
-
-    objects.push(Box::new(Cube {
-        center: Vec3::new(4.0, 1.0, 1.0),
-        length: 1,
-        material: chest_tetxure.clone().into(),
-    }));
-    
-    objects.push(Box::new(Cube {
-        center: Vec3::new(1.0, 0.0, 1.0),
-        length: 1,
-        material: cobblestone.clone().into(),
-    }));
-
-    let mut camera = Camera {
-        eye: vec3(0.0, 0.0, 10.0),    // Posición inicial de la cámara
-        center: vec3(0.0, 0.0, 0.0),  // Punto que la cámara está mirando
-        up: vec3(0.0, 1.0, 0.0),      // Vector "up"
-    };
-
-    let light = Light::new(
-        Vec3::new(10.0, 10.0, 10.0),
-        Color::new(255,255,255),
-        1.0,
-    );
 
     let mut window = Window::new(
         "3D Camera",
@@ -300,37 +221,237 @@ fn main() {
         panic!("{}", e);
     });
 
+    let mut camera_mode = CameraMode::Orbit;
+    window.set_title(&window_title(&render_settings, camera_mode));
+
+    // Vista previa a baja resolución para cuando la cámara se mueve
+    let mut preview = Framebuffer::new((width / PREVIEW_SCALE).max(1), (height / PREVIEW_SCALE).max(1));
+    let mut preview_settings = render_settings.clone();
+    preview_settings.samples = 1;
+    preview_settings.pattern = SamplePattern::Regular;
+    preview_settings.filter = PixelFilter::Box;
+
+    let mut last_frame = Instant::now();
+    let mut last_mouse: Option<(f32, f32)> = None;
+
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        framebuffer.clear();
-        
-        framebuffer.draw_background(&camera);
-        
-        // Rotación de la cámara
-        if window.is_key_down(Key::A) {
-            camera.orbit(0.05, 0.0);  // Rotar en el eje Y (yaw) hacia la izquierda
+        let previous_camera = camera.clone();
+
+        // Segundos desde el cuadro anterior, para que el vuelo no dependa de los FPS. Se limita
+        // para que un cuadro muy lento no haga saltar la cámara.
+        let now = Instant::now();
+        let dt = now.duration_since(last_frame).as_secs_f32().min(0.25);
+        last_frame = now;
+
+        // Tab alterna entre orbitar alrededor de `center` y volar libremente
+        if window.is_key_pressed(Key::Tab, KeyRepeat::No) {
+            camera_mode = match camera_mode {
+                CameraMode::Orbit => CameraMode::Fly,
+                CameraMode::Fly => CameraMode::Orbit,
+            };
+            last_mouse = None;
+            window.set_title(&window_title(&render_settings, camera_mode));
         }
-        if window.is_key_down(Key::D) {
-            camera.orbit(-0.05, 0.0);  // Rotar en el eje Y (yaw) hacia la derecha
+
+        match camera_mode {
+            CameraMode::Orbit => {
+                // Rotación de la cámara
+                if window.is_key_down(Key::A) {
+                    camera.orbit(0.05, 0.0);  // Rotar en el eje Y (yaw) hacia la izquierda
+                }
+                if window.is_key_down(Key::D) {
+                    camera.orbit(-0.05, 0.0);  // Rotar en el eje Y (yaw) hacia la derecha
+                }
+                if window.is_key_down(Key::W) {
+                    camera.orbit(0.0, 0.05);  // Rotar en el eje X (pitch) hacia arriba
+                }
+                if window.is_key_down(Key::S) {
+                    camera.orbit(0.0, -0.05);  // Rotar en el eje X (pitch) hacia abajo
+                }
+
+                //Changing camera zoom
+                if window.is_key_down(Key::Up) {
+                    camera.zoom(-0.5); //Zoom in
+                }
+
+                if window.is_key_down(Key::Down) {
+                    camera.zoom(0.5); //Zoom out
+                }
+            }
+            CameraMode::Fly => {
+                // Shift acelera y Ctrl frena
+                let mut speed = FLY_SPEED * dt;
+                if window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift) {
+                    speed *= 4.0;
+                }
+                if window.is_key_down(Key::LeftCtrl) || window.is_key_down(Key::RightCtrl) {
+                    speed *= 0.25;
+                }
+
+                let axis = |positive: Key, negative: Key| {
+                    window.is_key_down(positive) as i32 as f32 - window.is_key_down(negative) as i32 as f32
+                };
+                let forward = axis(Key::W, Key::S);
+                let right = axis(Key::D, Key::A);
+                let up = axis(Key::E, Key::Q);
+                if forward != 0.0 || right != 0.0 || up != 0.0 {
+                    camera.fly(forward * speed, right * speed, up * speed);
+                }
+
+                // Mirar arrastrando con el botón izquierdo: minifb no puede capturar el cursor
+                let mouse = window.get_mouse_pos(MouseMode::Pass);
+                if window.get_mouse_down(MouseButton::Left) {
+                    if let (Some((x, y)), Some((last_x, last_y))) = (mouse, last_mouse) {
+                        let (dx, dy) = (x - last_x, y - last_y);
+                        if dx != 0.0 || dy != 0.0 {
+                            camera.look(dx * MOUSE_SENSITIVITY, -dy * MOUSE_SENSITIVITY);
+                        }
+                    }
+                    last_mouse = mouse;
+                } else {
+                    last_mouse = None;
+                }
+            }
+        }
+
+        // O alterna entre perspectiva y ortográfica; I coloca la vista isométrica
+        if window.is_key_pressed(Key::O, KeyRepeat::No) {
+            let projection = match camera.projection {
+                Projection::Perspective => Projection::Orthographic,
+                _ => Projection::Perspective,
+            };
+            camera.set_projection(projection, aspect_ratio);
         }
-        if window.is_key_down(Key::W) {
-            camera.orbit(0.0, 0.05);  // Rotar en el eje X (pitch) hacia arriba
+        if window.is_key_pressed(Key::I, KeyRepeat::No) {
+            camera.make_isometric(aspect_ratio);
         }
-        if window.is_key_down(Key::S) {
-            camera.orbit(0.0, -0.05);  // Rotar en el eje X (pitch) hacia abajo
+
+        // Profundidad de campo: G enfoca lo que hay en el centro de la imagen y [ / ] cierran o
+        // abren la apertura
+        if window.is_key_pressed(Key::G, KeyRepeat::No) {
+            if let Some(distance) = autofocus(&objects, &camera) {
+                camera.focus_distance = distance;
+            }
         }
-        
-        //Changing camera zoom
-        if window.is_key_down(Key::Up) {
-            camera.zoom(-0.5); //Zoom in
+        if window.is_key_pressed(Key::LeftBracket, KeyRepeat::Yes) {
+            camera.aperture = (camera.aperture - 0.02).max(0.0);
         }
-    
-        if window.is_key_down(Key::Down) {
-            camera.zoom(0.5); //Zoom out
+        if window.is_key_pressed(Key::RightBracket, KeyRepeat::Yes) {
+            camera.aperture += 0.02;
         }
 
-        // Renderiza la escena con la posición actual de la cámara
-        render(&mut framebuffer, &objects, &camera, &light);
+        // Muestreo: P cambia el patrón, F el filtro y +/- el número de muestras por píxel
+        let mut sampling_changed = false;
+        if window.is_key_pressed(Key::P, KeyRepeat::No) {
+            render_settings.pattern = next_option(&SamplePattern::ALL, render_settings.pattern);
+            sampling_changed = true;
+        }
+        if window.is_key_pressed(Key::F, KeyRepeat::No) {
+            render_settings.filter = next_option(&PixelFilter::ALL, render_settings.filter);
+            sampling_changed = true;
+        }
+        if window.is_key_pressed(Key::Equal, KeyRepeat::No) {
+            render_settings.samples = (render_settings.samples * 2).min(256);
+            sampling_changed = true;
+        }
+        if window.is_key_pressed(Key::Minus, KeyRepeat::No) {
+            render_settings.samples = (render_settings.samples / 2).max(1);
+            sampling_changed = true;
+        }
+        if sampling_changed {
+            window.set_title(&window_title(&render_settings, camera_mode));
+            framebuffer.reset_accumulation();
+        }
+
+        if camera != previous_camera {
+            // En movimiento: descartar lo acumulado y mostrar sólo la vista previa
+            framebuffer.reset_accumulation();
+            draw_frame(&mut preview, &objects, &camera, &scene.lights, &preview_settings, stereo.as_ref());
+            framebuffer.upscale_from(&preview);
+        } else if framebuffer.accumulated_passes() < MAX_PROGRESSIVE_PASSES {
+            // Cámara quieta: otra pasada con muestras distintas que se promedia con las anteriores
+            let pass_settings = progressive_pass(&render_settings, framebuffer.accumulated_passes());
+            draw_frame(&mut framebuffer, &objects, &camera, &scene.lights, &pass_settings, stereo.as_ref());
+            framebuffer.accumulate();
+        } else {
+            // La imagen ya convergió: sólo atender la ventana
+            window.update();
+            continue;
+        }
 
+        framebuffer.resolve(&options.tone_mapping);
         window.update_with_buffer(framebuffer.get_buffer(), width, height).unwrap();
     }
-}
\ No newline at end of file
+}
+
+// Dibuja el fondo y la escena vista desde `camera`, o el par estéreo si se pidió
+fn draw_frame(
+    framebuffer: &mut Framebuffer,
+    scene: &Bvh,
+    camera: &Camera,
+    lights: &[Light],
+    settings: &RenderSettings,
+    stereo: Option<&Stereo>,
+) {
+    framebuffer.clear();
+    match stereo {
+        Some(stereo) => render_stereo(framebuffer, scene, camera, lights, settings, stereo),
+        None => {
+            framebuffer.draw_background(camera);
+            render(framebuffer, scene, camera, lights, settings);
+        }
+    }
+}
+
+// Nombre del archivo del cuadro `frame`: la secuencia de `#` de `pattern` se sustituye por el
+// número con ceros a la izquierda (`frame_####.png` → `frame_0012.png`). Sin `#`, el número se
+// añade antes de la extensión con cuatro cifras.
+fn frame_path(pattern: &str, frame: usize) -> String {
+    if let Some(start) = pattern.find('#') {
+        let digits = pattern[start..].chars().take_while(|&c| c == '#').count();
+        return format!("{}{:0width$}{}", &pattern[..start], frame, &pattern[start + digits..], width = digits);
+    }
+
+    let path = std::path::Path::new(pattern);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => path
+            .with_file_name(format!("{}_{:04}.{}", stem.to_string_lossy(), frame, extension.to_string_lossy()))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{}_{:04}", pattern, frame),
+    }
+}
+
+// Ajustes de la pasada progresiva número `pass`. La primera usa el patrón elegido tal cual; las
+// siguientes cambian de semilla y, si el patrón es la cuadrícula regular, pasan a jitter
+// estratificado para que cada pasada aporte muestras nuevas.
+fn progressive_pass(settings: &RenderSettings, pass: u32) -> RenderSettings {
+    let mut pass_settings = settings.clone();
+    if pass > 0 {
+        pass_settings.seed = settings.seed.wrapping_add(pass as u64);
+        if pass_settings.pattern == SamplePattern::Regular {
+            pass_settings.pattern = SamplePattern::Stratified;
+        }
+    }
+    pass_settings
+}
+
+fn window_title(settings: &RenderSettings, mode: CameraMode) -> String {
+    let mode = match mode {
+        CameraMode::Orbit => "orbit",
+        CameraMode::Fly => "fly",
+    };
+    format!(
+        "3D Camera - {} - {} spp, {} / {}",
+        mode,
+        settings.samples,
+        settings.pattern.name(),
+        settings.filter.name()
+    )
+}
+
+// Siguiente opción de la lista, volviendo al principio tras la última
+fn next_option<T: Copy + PartialEq>(options: &[T], current: T) -> T {
+    let index = options.iter().position(|&option| option == current).unwrap_or(0);
+    options[(index + 1) % options.len()]
+}
@@ -1,15 +1,46 @@
 use crate::color::Color;
-use crate::texture::Texture;
+use crate::texture::{Texel, Texture, TextureFilter, WrapMode};
+use nalgebra_glm::Vec3;
 use std::sync::Arc;
 
-#[derive(Debug, Clone)] 
+// Qué se hace con el canal alfa de las texturas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    // Se ignora: la superficie es opaca en todos sus puntos
+    Opaque,
+    // Recorte: los texels con alfa menor que `alpha_cutoff` no existen y los rayos, también
+    // los de sombra, pasan a través. Para hojas, rejas, ...
+    Mask,
+    // Translucidez: el color de la superficie se mezcla con lo que hay detrás según el alfa
+    // multiplicado por `opacity`. Para vidrieras, hielo, ...
+    Blend,
+}
+
+impl AlphaMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "opaque" => Some(AlphaMode::Opaque),
+            "mask" => Some(AlphaMode::Mask),
+            "blend" => Some(AlphaMode::Blend),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Material {
     pub diffuse: Color,
     pub specular: f32,
     pub albedo: [f32; 4],
     pub refractive_index: f32,
-    pub textures: [Option<Arc<Texture>>; 6], 
+    pub textures: [Option<Arc<Texture>>; 6],
     pub has_texture: bool,
+    pub texture_filter: TextureFilter,
+    pub wrap_mode: WrapMode,
+    pub uv_scale: f32, // Veces que se repite la textura a lo ancho y a lo alto de cada cara
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32, // Umbral del modo `Mask`
+    pub opacity: f32,      // Multiplica el alfa en el modo `Blend`
 }
 
 impl Material {
@@ -28,6 +59,12 @@ impl Material {
             refractive_index,
             textures,
             has_texture,
+            texture_filter: TextureFilter::Nearest,
+            wrap_mode: WrapMode::Clamp,
+            uv_scale: 1.0,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            opacity: 1.0,
         }
     }
 
@@ -39,28 +76,75 @@ impl Material {
         }
     }
 
-    pub fn black() -> Self {
-        Material {
-            diffuse: Color::new(0, 0, 0),
-            specular: 0.0,
-            albedo: [0.0, 0.0, 0.0, 0.0],
-            refractive_index: 1.0,
-            textures: [None,None,None,None,None,None],
-            has_texture: false,
+    // Color y opacidad de la superficie. `footprint` es el tamaño en UV de la zona que cubre el
+    // rayo (0 si no se conoce). El alfa ya tiene aplicado el modo: 1 salvo en `Blend`.
+    pub fn get_diffuse(&self, face_index: usize, u: f32, v: f32, footprint: f32) -> Texel {
+        let texel = self.sample(face_index, u, v, footprint);
+        match self.alpha_mode {
+            AlphaMode::Blend => Texel { color: texel.color, alpha: texel.alpha * self.opacity },
+            _ => Texel::opaque(texel.color),
         }
     }
 
-    pub fn get_diffuse_color(&self, face_index: usize, u: f32, v: f32) -> Color {
+    // Indica si el punto de coordenadas `(u, v)` de `Intersect`, en la cara con normal `normal`,
+    // está recortado por el canal alfa. Se usa el mipmap más fino para que el recorte no
+    // dependa de la distancia.
+    pub fn is_cut_out(&self, normal: &Vec3, u: f32, v: f32) -> bool {
+        self.alpha_mode == AlphaMode::Mask
+            && self.sample(face_index(normal), u, 1.0 - v, 0.0).alpha < self.alpha_cutoff
+    }
+
+    // Las UV de `Intersect` recorren cada cara una vez; `uv_scale` las estira para repetir la
+    // textura según `wrap_mode`
+    fn sample(&self, face_index: usize, u: f32, v: f32, footprint: f32) -> Texel {
         if self.has_texture {
             if let Some(ref texture) = self.get_texture_for_face(face_index) {
-                let x = u * (texture.width as f32 - 1.0);
-                let y = v * (texture.height as f32 - 1.0);
-                texture.get_color(x as usize, y as usize)
+                let scale = self.uv_scale;
+                texture.sample(u * scale, v * scale, footprint * scale, self.texture_filter, self.wrap_mode)
             } else {
-                self.diffuse
+                Texel::opaque(self.diffuse)
             }
         } else {
-            self.diffuse
+            Texel::opaque(self.diffuse)
         }
     }
 }
+
+// Cara de un bloque según su normal, en el orden de `Material::textures`: derecha, izquierda,
+// arriba, abajo, frente, atrás
+pub fn face_index(normal: &Vec3) -> usize {
+    if normal.x.abs() > 0.9 {
+        if normal.x > 0.0 { 0 } else { 1 } // Caras derecha e izquierda
+    } else if normal.y.abs() > 0.9 {
+        if normal.y > 0.0 { 2 } else { 3 } // Caras superior e inferior
+    } else if normal.z.abs() > 0.9 {
+        if normal.z > 0.0 { 4 } else { 5 } // Caras frontal y trasera
+    } else {
+        0 // Default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uv_scale_tiles_the_texture_on_each_face() {
+        // Textura de 2x1, negro y blanco: con `uv_scale` 2 el par se repite dos veces por cara
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        let texture = Arc::new(Texture::new(2, 1, vec![Texel::opaque(Color::black()), Texel::opaque(white)]));
+        let textures = std::array::from_fn(|_| Some(texture.clone()));
+        let mut material = Material::new(white, 0.0, [1.0, 0.0, 0.0, 0.0], 1.0, textures, true);
+        let gray_at = |material: &Material, u: f32| material.get_diffuse(0, u, 0.5, 0.0).color.r();
+
+        assert_eq!([0.2, 0.4, 0.6, 0.8].map(|u| gray_at(&material, u)), [0.0, 0.0, 1.0, 1.0]);
+
+        material.uv_scale = 2.0;
+        material.wrap_mode = WrapMode::Repeat;
+        assert_eq!([0.2, 0.4, 0.6, 0.8].map(|u| gray_at(&material, u)), [0.0, 1.0, 0.0, 1.0]);
+
+        // Con Clamp la textura no se repite: la segunda mitad se queda en el texel del borde
+        material.wrap_mode = WrapMode::Clamp;
+        assert_eq!([0.2, 0.4, 0.6, 0.8].map(|u| gray_at(&material, u)), [0.0, 1.0, 1.0, 1.0]);
+    }
+}
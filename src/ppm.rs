@@ -0,0 +1,27 @@
+// ppm.rs
+
+use std::fs::File;
+use std::io::{Write, BufWriter, Result};
+
+// Escribe un PPM binario (P6): cabecera de texto seguida de bytes RGB, fila por fila desde arriba
+pub fn write_ppm_file(
+    file_path: &str,
+    buffer: &[u32],
+    width: usize,
+    height: usize,
+) -> Result<()> {
+    let file = File::create(file_path)?;
+    let mut writer = BufWriter::new(file);
+
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+
+    for pixel in &buffer[..width * height] {
+        let r = ((pixel >> 16) & 0xFF) as u8;
+        let g = ((pixel >> 8) & 0xFF) as u8;
+        let b = (pixel & 0xFF) as u8;
+        writer.write_all(&[r, g, b])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
@@ -5,10 +5,12 @@ use crate::figures::Sphere;
 use crate::figures::Cube;
 use crate::material::Material;
 use crate::intersect::{Intersect, RayIntersect}; // Importa Intersect y RayIntersect
+use crate::bvh::Aabb;
 
 pub trait Renderable: Send + Sync{
     fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect;
     fn get_normal(&self, point: &Vec3) -> Vec3;
+    fn bounding_box(&self) -> Aabb;
 }
 
 impl Renderable for Sphere {
@@ -41,27 +43,24 @@ impl Renderable for Sphere {
     fn get_normal(&self, point: &Vec3) -> Vec3 {
         Vec3::new(0.0, 0.0, 0.0) // Normal predeterminada (sin colisión detectada)
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - extent, self.center + extent)
+    }
 }
 
 impl Renderable for Cube {
     fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
-        // Evitar división por cero
-        if ray_direction.x.abs() < 1e-6 || ray_direction.y.abs() < 1e-6 || ray_direction.z.abs() < 1e-6 {
-            return Intersect::empty(); // No hay intersección si el rayo no tiene dirección
-        }
-
+        // Con componentes nulas la inversa es infinita y la prueba de "slabs" sigue funcionando
         let inv_dir = Vec3::new(1.0 / ray_direction.x, 1.0 / ray_direction.y, 1.0 / ray_direction.z);
-        let half_length = self.length as f32 / 2.0;
-        let bounds = [
-            self.center - Vec3::new(half_length, half_length, half_length),
-            self.center + Vec3::new(half_length, half_length, half_length),
-        ];
+        let bounds = [self.min(), self.max()];
 
         // Calcular tmin y tmax para el eje x
         let (mut tmin, mut tmax) = calculate_t(bounds[0].x, bounds[1].x, ray_origin.x, inv_dir.x);
 
         // Calcular tymin y tymax para el eje y
-        let (mut tymin, mut tymax) = calculate_t(bounds[0].y, bounds[1].y, ray_origin.y, inv_dir.y);
+        let (tymin, tymax) = calculate_t(bounds[0].y, bounds[1].y, ray_origin.y, inv_dir.y);
 
         // Comprobar si hay intersección
         if (tmin > tymax) || (tymin > tmax) {
@@ -73,7 +72,7 @@ impl Renderable for Cube {
         tmax = tmax.min(tymax);
 
         // Calcular tzmin y tzmax para el eje z
-        let (mut tzmin, mut tzmax) = calculate_t(bounds[0].z, bounds[1].z, ray_origin.z, inv_dir.z);
+        let (tzmin, tzmax) = calculate_t(bounds[0].z, bounds[1].z, ray_origin.z, inv_dir.z);
 
         // Comprobar si hay intersección
         if (tmin > tzmax) || (tzmin > tmax) {
@@ -84,26 +83,38 @@ impl Renderable for Cube {
         tmin = tmin.max(tzmin);
         tmax = tmax.min(tzmax);
 
-        // Si llegamos aquí, hay una intersección
-        if tmin < 0.0 {
-            return Intersect::empty(); // Ignorar intersecciones detrás del origen
+        if tmax < 0.0 {
+            return Intersect::empty(); // El cubo está detrás del origen
         }
 
-        let distance = tmin; // La distancia más cercana
-        let point = ray_origin + ray_direction * distance;
-        let normal = self.get_normal(&point); // Calcular la normal en el punto de intersección
-
-        let (u, v) = self.get_uv(&point, &normal);
-
-        Intersect {
-            is_intersecting: true,
-            distance,
-            point,
-            normal,
-            material: Some(self.material.clone()),
-            u,
-            v,
+        // Si el origen está dentro del cubo, el rayo lo toca al salir. Si entra por una zona
+        // recortada por el alfa de la textura, también puede tocarlo al salir.
+        for distance in [tmin, tmax] {
+            if distance < 0.0 {
+                continue;
+            }
+            let point = ray_origin + ray_direction * distance;
+            let normal = self.get_normal(&point); // Calcular la normal en el punto de intersección
+
+            let (u, v) = self.get_uv(&point, &normal);
+
+            let intersect = Intersect {
+                is_intersecting: true,
+                distance,
+                point,
+                normal,
+                material: Some(self.material.clone()),
+                u,
+                v,
+                // Las UV recorren una cara entera, de lado `length`
+                uv_scale: 1.0 / self.length as f32,
+            };
+            if !intersect.is_cut_out() {
+                return intersect;
+            }
         }
+
+        Intersect::empty()
     }
 
     fn get_normal(&self, point: &Vec3) -> Vec3 {
@@ -132,10 +143,24 @@ impl Renderable for Cube {
         // podría ser un error en la intersección, pero para completar:
         Vec3::new(0.0, 0.0, 0.0) // Normal predeterminada (sin colisión detectada)
     }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(self.min(), self.max())
+    }
 }
 
 // Función auxiliar para calcular tmin y tmax
 fn calculate_t(min_bound: f32, max_bound: f32, ray_origin: f32, inv_dir: f32) -> (f32, f32) {
+    // Un rayo paralelo a este eje no lo limita mientras el origen esté entre los planos
+    // (evita el NaN de 0 * infinito)
+    if inv_dir.is_infinite() {
+        return if ray_origin >= min_bound && ray_origin <= max_bound {
+            (f32::NEG_INFINITY, f32::INFINITY)
+        } else {
+            (f32::INFINITY, f32::NEG_INFINITY)
+        };
+    }
+
     let tmin = (min_bound - ray_origin) * inv_dir;
     let tmax = (max_bound - ray_origin) * inv_dir;
 
@@ -1,230 +1,702 @@
 use crate::framebuffer::Framebuffer;
 use crate::color::Color;
 use crate::intersect::Intersect;
-use crate::figures::Sphere;
-use crate::intersect::RayIntersect;
-use crate::light::Light;
-use nalgebra_glm::{Vec3, normalize};
+use crate::material::{face_index, AlphaMode};
+use crate::light::{Light, LightKind};
+use crate::sampling::{hash_point, mix, stratified, PixelFilter, Rng, SamplePattern};
+use nalgebra_glm::Vec3;
 use std::f32;
 use std::ops::Add;
 use std::ops::Mul;
-use crate::camera::Camera;
-use crate::ray_intersect::Renderable;
-use crate::texture::Texture;
-use rayon::iter::IntoParallelIterator;
+use crate::camera::{Camera, Stereo, StereoMode};
+use crate::bvh::Bvh;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use std::sync::Arc;
+
+// Rebotes máximos de reflexión y refracción
+const MAX_DEPTH: u32 = 5;
+
+// Sal para la semilla de las muestras de la lente
+const LENS_SEED: u64 = 0x4C45_4E53;
+
+// Opciones del renderizador paralelo
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub tile_size: usize, // Filas de píxeles que procesa cada tarea
+    pub samples: u32,     // Rayos por píxel; el patrón regular lo redondea a un cuadrado
+    pub pattern: SamplePattern,
+    pub filter: PixelFilter,
+    pub seed: u64,        // Semilla de los patrones aleatorios, para resultados reproducibles
+    thread_pool: Option<Arc<ThreadPool>>,
+}
+
+// Rayos vecinos de un rayo, desplazados la distancia entre muestras en x y en y sobre la imagen.
+// Lo que se separan al llegar a una superficie es el tamaño de la zona que cubre el rayo, y de
+// ahí sale el mipmap de la textura.
+#[derive(Debug, Clone, Copy)]
+struct RayDifferential {
+    x: (Vec3, Vec3), // Origen y dirección
+    y: (Vec3, Vec3),
+}
+
+impl RayDifferential {
+    // Lleva los rayos vecinos hasta el plano tangente en el punto de impacto. Falla si alguno
+    // es paralelo al plano o lo corta por detrás.
+    fn transfer(&self, point: &Vec3, normal: &Vec3) -> Option<RayDifferential> {
+        let hit = |(origin, direction): (Vec3, Vec3)| {
+            let denominator = direction.dot(normal);
+            if denominator.abs() < 1e-6 {
+                return None;
+            }
+            let t = (point - origin).dot(normal) / denominator;
+            (t > 0.0).then(|| (origin + direction * t, direction))
+        };
+        Some(RayDifferential { x: hit(self.x)?, y: hit(self.y)? })
+    }
+
+    // Distancia mayor entre `point` y los orígenes de los vecinos, una vez transferidos
+    fn footprint(&self, point: &Vec3) -> f32 {
+        (self.x.0 - point).magnitude().max((self.y.0 - point).magnitude())
+    }
+
+    // Vecinos de un rayo secundario: salen de donde impactaron, con la dirección desviada por
+    // `bend` (reflexión o refracción)
+    fn bend<F: Fn(&Vec3) -> Option<Vec3>>(&self, bend: F) -> Option<RayDifferential> {
+        Some(RayDifferential {
+            x: (self.x.0, bend(&self.x.1)?),
+            y: (self.y.0, bend(&self.y.1)?),
+        })
+    }
+}
+
+impl RenderSettings {
+    // `threads == 0` usa el pool global de rayon (un hilo por núcleo). Falla si el sistema no
+    // puede crear los hilos pedidos.
+    pub fn new(tile_size: usize, threads: usize) -> Result<Self, ThreadPoolBuildError> {
+        let thread_pool = if threads == 0 {
+            None
+        } else {
+            let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+            Some(Arc::new(pool))
+        };
+
+        Ok(RenderSettings { tile_size: tile_size.max(1), thread_pool, ..RenderSettings::default() })
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            tile_size: 8,
+            samples: 1,
+            pattern: SamplePattern::Regular,
+            filter: PixelFilter::Box,
+            seed: 0,
+            thread_pool: None,
+        }
+    }
+}
 
 pub fn render(
     framebuffer: &mut Framebuffer,
-    objects: &[Box<dyn Renderable>],
+    scene: &Bvh,
     camera: &Camera,
-    light: &Light,
+    lights: &[Light],
+    settings: &RenderSettings,
 ) {
-    let width = framebuffer.get_width() as f32;
-    let height = framebuffer.get_height() as f32;
-    let aspect_ratio = width / height;
-
-    let mut z_buffer = vec![f32::INFINITY; (width * height) as usize];
-    let mut pixel_colors = vec![(Color::new(0, 0, 0), f32::INFINITY); (width * height) as usize];
-
-    let norm_x = 2.0 / width;
-    let norm_y = 2.0 / height;
-
-    // Usar iteradores paralelos para procesar cada píxel
-    (0..(width * height) as usize).into_iter().for_each(|pixel_index| {
-        let y = pixel_index / width as usize; 
-        let x = pixel_index % width as usize; 
-
-        let screen_x = (norm_x * x as f32) - 1.0;
-        let screen_y = -((norm_y * y as f32) - 1.0) * aspect_ratio;
-
-        let ray_camera_space = Vec3::new(screen_x, screen_y, -1.0).normalize();
-        let ray_direction = camera.basis_change(&ray_camera_space);
-        let ray_origin = camera.eye;
-
-        // Pasar referencias directamente
-        let object_refs: Vec<&dyn Renderable> = objects.iter().map(|obj| obj.as_ref()).collect();
-        let (pixel_color, z) = cast_ray(&ray_origin, &ray_direction, &object_refs, light, 5);
-
-        // Actualizar el vector temporal de colores
-        pixel_colors[pixel_index] = (pixel_color, z);
-    });
-
-    // Actualizar el framebuffer y el z_buffer en la segunda pasada
-    for pixel_index in 0..(width * height) as usize {
-        let (pixel_color, z) = pixel_colors[pixel_index];
-        if z < z_buffer[pixel_index] {
-            let y = pixel_index / width as usize; 
-            let x = pixel_index % width as usize; 
-            framebuffer.set_current_color(pixel_color);
-            framebuffer.point(x.try_into().unwrap(), y.try_into().unwrap());
-            z_buffer[pixel_index] = z;
+    let width = framebuffer.get_width();
+    let height = framebuffer.get_height();
+    let tile_rows = settings.tile_size.max(1);
+    let filter = settings.filter;
+    let radius = filter.radius();
+    // Separación entre muestras vecinas, en píxeles, para los diferenciales de los rayos
+    let spacing = 2.0 * radius / (settings.samples.max(1) as f32).sqrt();
+
+    // La cuadrícula regular es igual en todos los píxeles: se calcula una sola vez. Con una
+    // sola muestra el rayo pasa por la esquina del píxel, como siempre.
+    let shared_samples = match settings.pattern {
+        SamplePattern::Regular if settings.samples <= 1 => Some(vec![pixel_corner_sample(radius)]),
+        SamplePattern::Regular => Some(settings.pattern.generate(settings.samples, 0)),
+        _ => None,
+    };
+
+    // Cada tarea escribe sólo en su propia franja de filas del framebuffer, así que el
+    // resultado no depende del número de hilos ni del orden de ejecución
+    let render_tile = |(tile_index, tile): (usize, &mut [Color])| {
+        let first_row = tile_index * tile_rows;
+
+        for (offset, pixel) in tile.iter_mut().enumerate() {
+            let x = offset % width;
+            let y = first_row + offset / width;
+
+            // Semilla fija por píxel: la misma imagen en cada ejecución, sea cual sea el reparto
+            // entre hilos
+            let pixel_seed = mix(settings.seed ^ (y * width + x) as u64);
+            let pixel_samples = match &shared_samples {
+                Some(samples) => samples.clone(),
+                None => settings.pattern.generate(settings.samples, pixel_seed),
+            };
+            // Posiciones en la lente, independientes de las del píxel para no correlacionarlas
+            let mut lens_rng = Rng::new(pixel_seed ^ LENS_SEED);
+
+            // Los rayos que no tocan nada dejan ver el fondo ya dibujado
+            let background = *pixel;
+            let mut hits = 0;
+            let mut sum = Color::black();
+            let mut total_weight = 0.0;
+
+            for (u, v) in pixel_samples {
+                // Repartir las muestras por todo el soporte del filtro, centrado en el píxel. Con
+                // Gaussian (radio 1.5) o Mitchell (radio 2) eso abarca 3x3 o 4x4 píxeles: cada
+                // píxel recoge algo de sus vecinos, ponderado por el filtro.
+                let (dx, dy) = sample_offset((u, v), radius);
+                let weight = filter.weight(dx, dy);
+
+                let lens = (lens_rng.next_f32(), lens_rng.next_f32());
+                let (sx, sy) = (x as f32 + 0.5 + dx, y as f32 + 0.5 + dy);
+                let (ray_origin, ray_direction) = camera.primary_ray(sx, sy, width, height, lens);
+                let differential = RayDifferential {
+                    x: camera.primary_ray(sx + spacing, sy, width, height, lens),
+                    y: camera.primary_ray(sx, sy + spacing, width, height, lens),
+                };
+                let (color, z) = cast_ray(&ray_origin, &ray_direction, Some(differential), scene, lights, MAX_DEPTH);
+                let color = if z.is_finite() {
+                    hits += 1;
+                    color
+                } else {
+                    background
+                };
+
+                sum = sum + color * weight;
+                total_weight += weight;
+            }
+
+            if hits > 0 && total_weight.abs() > f32::EPSILON {
+                // Los lóbulos negativos de Mitchell pueden dejar algún canal por debajo de cero
+                *pixel = (sum * (1.0 / total_weight)).map(|c| c.max(0.0));
+            }
         }
+    };
+
+    let buffer = framebuffer.get_hdr_buffer_mut();
+    match &settings.thread_pool {
+        Some(pool) => pool.install(|| {
+            buffer.par_chunks_mut(width * tile_rows).enumerate().for_each(render_tile)
+        }),
+        None => buffer.par_chunks_mut(width * tile_rows).enumerate().for_each(render_tile),
     }
 }
 
+// Renderiza la escena para los dos ojos y combina las imágenes en `framebuffer` según el modo.
+// A diferencia de `render`, dibuja también el fondo de cada ojo.
+pub fn render_stereo(
+    framebuffer: &mut Framebuffer,
+    scene: &Bvh,
+    camera: &Camera,
+    lights: &[Light],
+    settings: &RenderSettings,
+    stereo: &Stereo,
+) {
+    let width = framebuffer.get_width();
+    let height = framebuffer.get_height();
+    let (left_camera, right_camera) = stereo.eyes(camera);
+
+    // Cada ojo ocupa media imagen en paralelo y la imagen completa en anaglifo
+    let eye_width = match stereo.mode {
+        StereoMode::SideBySide => (width / 2).max(1),
+        StereoMode::Anaglyph => width,
+    };
+
+    let render_eye = |eye_camera: &Camera| {
+        let mut eye = Framebuffer::new(eye_width, height);
+        eye.clear();
+        eye.draw_background(eye_camera);
+        render(&mut eye, scene, eye_camera, lights, settings);
+        eye
+    };
+    let left = render_eye(&left_camera);
+    let right = render_eye(&right_camera);
+
+    match stereo.mode {
+        StereoMode::SideBySide => {
+            framebuffer.blit(&left, 0, 0);
+            framebuffer.blit(&right, width - eye_width, 0);
+        }
+        StereoMode::Anaglyph => {
+            let pixels = left.get_hdr_buffer().iter().zip(right.get_hdr_buffer());
+            for (pixel, (l, r)) in framebuffer.get_hdr_buffer_mut().iter_mut().zip(pixels) {
+                *pixel = Color::rgb(l.r(), r.g(), r.b());
+            }
+        }
+    }
+}
+
+// Distancia de enfoque para lo que se ve en el centro de la imagen, o `None` si ahí no hay nada
+pub fn autofocus(scene: &Bvh, camera: &Camera) -> Option<f32> {
+    let (origin, direction) = camera.primary_ray(0.5, 0.5, 1, 1, (0.0, 0.0));
+    let intersect = scene.intersect(&origin, &direction);
+    if intersect.is_intersecting {
+        Some(camera.depth_of(&intersect.point))
+    } else {
+        None
+    }
+}
+
+// Desplazamiento en píxeles desde el centro del píxel para la posición `(u, v)` en [0, 1)^2 de
+// una muestra, repartida por el soporte de un filtro de radio `radius`
+fn sample_offset((u, v): (f32, f32), radius: f32) -> (f32, f32) {
+    ((u - 0.5) * 2.0 * radius, (v - 0.5) * 2.0 * radius)
+}
+
+// Posición de la única muestra con la cuadrícula regular y una muestra por píxel: la que
+// `sample_offset` lleva a (-0.5, -0.5), la esquina superior izquierda del píxel, sea cual sea el
+// radio del filtro. Reproduce el rayo que lanzaba el renderizador antes de los patrones de
+// muestreo, para que la imagen por defecto no cambie.
+fn pixel_corner_sample(radius: f32) -> (f32, f32) {
+    let corner = 0.5 - 0.25 / radius;
+    (corner, corner)
+}
 
 fn cast_ray(
     ray_origin: &Vec3, 
     ray_direction: &Vec3, 
-    objects: &[&dyn Renderable], 
-    light: &Light,
+    differential: Option<RayDifferential>,
+    scene: &Bvh, 
+    lights: &[Light],
     depth: u32
 ) -> (Color, f32) {
     if depth == 0 {
         return (Color::new(0, 0, 0), f32::INFINITY); // Limitar la profundidad de las reflexiones y refracciones
     }    
 
-    let mut intersect = Intersect::empty();
-    let mut zbuffer = f32::INFINITY;
-
     // Buscar el objeto más cercano con el que el rayo intersecta
-    for object in objects {
-        let tmp = object.ray_intersect(ray_origin, ray_direction);
-        if tmp.is_intersecting && tmp.distance < zbuffer {
-            zbuffer = tmp.distance;
-            intersect = tmp;
-        }
+    let intersect = scene.intersect(ray_origin, ray_direction);
+
+    if !intersect.is_intersecting {
+        return (Color::new(4, 12, 36), f32::INFINITY); // Color de fondo
     }
 
-    if intersect.is_intersecting {
-        let normal = intersect.normal;
-        let mut color = Color::new(0, 0, 0);
-
-        let light_dir = (light.position - intersect.point).normalize();
-        let view_dir = (ray_origin - intersect.point).normalize();
-        let reflect_dir = reflect(&-light_dir, &intersect.normal);
-
-        // Calcular la intensidad de la sombra
-        let shadow_intensity = cast_shadow(&intersect, light, objects);
-        let light_intensity = light.intensity * (1.0 - shadow_intensity);
-
-        // Luz ambiental: color constante aplicado a todas las superficies
-        let ambient_light_intensity = 0.3; // Valor que puedes ajustar según tu preferencia
-        let ambient_light_color = Color::new(80, 80, 80); // Color ambiental (gris oscuro)
-
-        // Determinar la textura en función de las coordenadas UV o la normal
-        let texture_color = if let Some(ref material) = intersect.material {
-            let face_index = if normal.x.abs() > 0.9 {
-                if normal.x > 0.0 { 0 } else { 1 } // Caras derecha e izquierda
-            } else if normal.y.abs() > 0.9 {
-                if normal.y > 0.0 { 2 } else { 3 } // Caras superior e inferior
-            } else if normal.z.abs() > 0.9 {
-                if normal.z > 0.0 { 4 } else { 5 } // Caras frontal y trasera
-            } else {
-                0 // Default
-            };
+    let Some(ref material) = intersect.material else {
+        return (Color::new(0, 0, 0), intersect.distance); // Color negro si no hay material
+    };
 
-            material.get_diffuse_color(face_index, intersect.u, 1.0 - intersect.v)
-        } else {
-            Color::new(0, 0, 0) // Color negro si no hay material
-        };
+    let normal = intersect.normal;
+    let view_dir = (ray_origin - intersect.point).normalize();
+
+    // Determinar la textura en función de las coordenadas UV o la normal
+    let face_index = face_index(&normal);
+
+    // Tamaño en UV de la zona que cubre el rayo; sin diferenciales se usa la textura completa
+    let differential = differential.and_then(|d| d.transfer(&intersect.point, &normal));
+    let footprint = differential.map_or(0.0, |d| d.footprint(&intersect.point) * intersect.uv_scale);
+    let texel = material.get_diffuse(face_index, intersect.u, 1.0 - intersect.v, footprint);
+    let texture_color = texel.color;
+
+    // Luz ambiental: color constante aplicado a todas las superficies, más un mínimo de la
+    // textura para que las caras que no reciben luz directa no queden planas
+    let ambient_light_intensity = 0.3; // Valor que puedes ajustar según tu preferencia
+    let ambient_light_color = Color::rgb(0.08, 0.08, 0.08); // Color ambiental lineal (gris oscuro)
+    let ambient = ambient_light_color.mul(ambient_light_intensity).add(texture_color.mul(0.2));
+
+    let mut diffuse = Color::new(0, 0, 0);
+    let mut specular = Color::new(0, 0, 0);
+
+    // Acumular la contribución de cada luz, con su propio rayo de sombra
+    for light in lights {
+        let (light_dir, _) = light.direction_from(&intersect.point);
+        let reflect_dir = reflect(&-light_dir, &normal);
+
+        // Calcular qué fracción de la luz no está bloqueada
+        let visibility = cast_shadow(&intersect, light, scene);
+        let light_intensity = light.intensity * light.attenuation(&intersect.point) * visibility;
+        if light_intensity <= 0.0 {
+            continue;
+        }
 
         // Intensidad difusa
-        let diffuse_intensity = normal.dot(&light_dir).max(0.2);
-
-        let ambient = ambient_light_color.mul(ambient_light_intensity);
-        let diffuse = texture_color.mul(diffuse_intensity * light_intensity);
+        let diffuse_intensity = normal.dot(&light_dir).max(0.0);
+        diffuse = diffuse.add((texture_color * light.color).mul(diffuse_intensity * light_intensity));
 
-        if let Some(ref material) = intersect.material {
-            // Intensidad especular
-            let specular_intensity = view_dir.dot(&reflect_dir).max(0.0).powf(material.specular);
-            let specular = light.color * material.albedo[1] * specular_intensity * light_intensity;
+        // Intensidad especular; `material.specular` es el exponente de brillo
+        let specular_intensity = view_dir.dot(&reflect_dir).max(0.0).powf(material.specular);
+        specular = specular.add(light.color * (specular_intensity * light_intensity));
+    }
 
-            // Reflexión
-            if material.albedo[2] > 0.0 {
-                let reflection_color = cast_ray(&intersect.point, &reflect(&ray_direction, &normal), objects, light, depth - 1).0;
-                color = color.add(reflection_color.mul(material.albedo[2]));
-            }
+    // Componente local: ambiental + difusa (albedo[0]) + especular (albedo[1])
+    let local = ambient
+        .add(diffuse.mul(material.albedo[0]))
+        .add(specular.mul(material.albedo[1]));
 
-            // Refracción
-            if material.albedo[3] > 0.0 {
-                let refraction_color = cast_ray_with_refraction(&intersect, &ray_direction, objects, light, depth - 1);
-                color = color.add(refraction_color.mul(material.albedo[3]));
-            }
+    let reflectivity = material.albedo[2];
+    let transparency = material.albedo[3];
 
-            // Sumar las componentes de luz ambiental, difusa y especular
-            let shadow_color = Color::new(20, 20, 20);
-            color = ambient.add(diffuse).add(shadow_color.mul(shadow_intensity)); // Agregar luz ambiental
-        }
+    // Lo que se refleja o se transmite ya no se ve como color propio de la superficie
+    let mut color = local.mul((1.0 - reflectivity - transparency).max(0.0));
 
-        (color, intersect.distance)
+    // Reflexión
+    let needs_reflection = reflectivity > 0.0 || transparency > 0.0;
+    let reflection_color = if needs_reflection {
+        let reflect_dir = reflect(ray_direction, &normal);
+        let reflect_origin = offset_origin(&intersect.point, &normal, &reflect_dir);
+        let reflect_differential = differential.and_then(|d| d.bend(|dir| Some(reflect(dir, &normal))));
+        cast_ray(&reflect_origin, &reflect_dir, reflect_differential, scene, lights, depth - 1).0
     } else {
-        (Color::new(4, 12, 36), f32::INFINITY) // Color de fondo
+        Color::new(0, 0, 0)
+    };
+
+    if reflectivity > 0.0 {
+        color = color.add(reflection_color.mul(reflectivity));
+    }
+
+    // Refracción, repartida con la reflexión según Fresnel
+    if transparency > 0.0 {
+        let (refraction_color, kr) = cast_ray_with_refraction(&intersect, ray_direction, differential, scene, lights, depth - 1);
+        let transmitted = reflection_color.mul(kr).add(refraction_color.mul(1.0 - kr));
+        color = color.add(transmitted.mul(transparency));
+    }
+
+    // Translucidez por alfa: lo que hay detrás se ve sin desviarse, en la proporción que la
+    // superficie deja pasar
+    if texel.alpha < 1.0 {
+        let behind_origin = offset_origin(&intersect.point, &normal, ray_direction);
+        let behind_differential = differential.and_then(|d| d.bend(|dir| Some(*dir)));
+        let (behind, _) = cast_ray(&behind_origin, ray_direction, behind_differential, scene, lights, depth - 1);
+        color = color.mul(texel.alpha).add(behind.mul(1.0 - texel.alpha));
     }
+
+    (color, intersect.distance)
 }
 
 fn reflect(incident: &Vec3, normal: &Vec3) -> Vec3 {
     incident - 2.0 * incident.dot(normal) * normal
 }
 
+// Fracción de la luz que llega al punto, de 0 (en sombra) a 1 (totalmente iluminado). Las luces
+// de área lanzan `light.samples` rayos estratificados hacia puntos distintos de su superficie.
 fn cast_shadow(
     intersect: &Intersect,
     light: &Light,
-    objects: &[&dyn Renderable],
+    scene: &Bvh,
 ) -> f32 {
-    // Calcular la dirección de la luz desde el punto de intersección
-    let light_dir = (light.position - intersect.point).normalize();
-    
-    // Desplazar ligeramente el origen del rayo de sombra para evitar "shadow acne"
-    let shadow_ray_origin = intersect.point + light_dir * 1e-3; 
+    // Las luces direccionales no tienen posición: un solo rayo sin límite de distancia
+    if let LightKind::Directional { .. } = light.kind {
+        let (light_dir, max_distance) = light.direction_from(&intersect.point);
+        return shadow_ray_visibility(&intersect.point, &light_dir, max_distance, scene);
+    }
 
-    // Variable para determinar si hay un objeto bloqueando la luz
-    let mut shadow_intensity: f32 = 0.0;
+    let mut rng = Rng::new(hash_point(&intersect.point));
+    let samples = stratified(light.samples, &mut rng);
 
-    // Determinamos la distancia máxima para comprobar si el objeto está dentro del rango de la luz
-    let max_distance = (light.position - intersect.point).norm();
+    let visible: f32 = samples
+        .iter()
+        .filter_map(|&(u, v)| light.sample_position(&intersect.point, u, v))
+        .map(|sample| {
+            let to_light = sample - intersect.point;
+            let max_distance = to_light.magnitude();
+            shadow_ray_visibility(&intersect.point, &(to_light / max_distance), max_distance, scene)
+        })
+        .sum();
 
-    for object in objects {
-        // Obtener la intersección con el rayo de sombra
-        let shadow_intersect = object.ray_intersect(&shadow_ray_origin, &light_dir);
+    visible / samples.len() as f32
+}
 
-        // Comprobar si hay una intersección y si está más cerca de lo que la luz puede alcanzar
-        if shadow_intersect.is_intersecting && shadow_intersect.distance < max_distance {
-            // Se encontró un objeto que bloquea la luz
-            shadow_intensity += 0.5; // Ajusta este valor para modificar la intensidad de la sombra
-            break; // Salimos del bucle si encontramos un objeto bloqueador
-        }
-        // println!("Shadow Ray Origin: {:?}", shadow_ray_origin);
-        // println!("Light Direction: {:?}", light_dir);
-        // println!("Max Distance: {:?}", max_distance);
-        // println!("Intersect Distance: {:?}", shadow_intersect.distance);
+fn shadow_ray_visibility(point: &Vec3, light_dir: &Vec3, max_distance: f32, scene: &Bvh) -> f32 {
+    // Desplazar ligeramente el origen del rayo de sombra para evitar "shadow acne"
+    let mut origin = point + light_dir * 1e-3;
+    let mut remaining = max_distance - 1e-3;
+
+    // La BVH se detiene en el primer objeto que bloquea la luz: si no hay ninguno, el punto
+    // está iluminado sin tener que buscar el más cercano
+    if !scene.occluded(&origin, light_dir, remaining) {
+        return 1.0;
     }
 
+    // Algo se interpone: las superficies translúcidas dejan pasar parte de la luz y se siguen
+    // atravesando; cualquier otra la bloquea del todo
+    let mut visibility = 1.0;
+    loop {
+        let intersect = scene.intersect(&origin, light_dir);
+        if !intersect.is_intersecting || intersect.distance >= remaining {
+            return visibility;
+        }
+        let Some(material) = intersect.material.as_ref().filter(|m| m.alpha_mode == AlphaMode::Blend) else {
+            return 0.0;
+        };
 
+        let texel = material.get_diffuse(face_index(&intersect.normal), intersect.u, 1.0 - intersect.v, 0.0);
+        visibility *= 1.0 - texel.alpha;
+        if visibility <= 0.0 {
+            return 0.0;
+        }
 
-    // Limitar la intensidad entre 0 y 1
-    shadow_intensity.clamp(0.0, 1.0)
+        origin = intersect.point + light_dir * 1e-3;
+        remaining -= intersect.distance + 1e-3;
+    }
 }
 
 
 
+// Color visto a través de la superficie y coeficiente de Fresnel (fracción reflejada). Si el rayo
+// viene desde dentro del material se invierten la normal y los índices de refracción.
 fn cast_ray_with_refraction(
     intersect: &Intersect, 
     ray_direction: &Vec3, 
-    objects: &[&dyn Renderable], 
-    light: &Light, 
+    differential: Option<RayDifferential>,
+    scene: &Bvh, 
+    lights: &[Light], 
     depth: u32
-) -> Color {
-
+) -> (Color, f32) {
     if depth == 0 {
-        return Color::new(0, 0, 0); // Limitar la profundidad
+        return (Color::new(0, 0, 0), 1.0); // Limitar la profundidad
     }
 
-    let normal = intersect.normal;
-    let n1 = 1.0; // Índice de refracción del aire
-    let n2 = intersect.material.clone().unwrap().refractive_index; // Índice del material
+    let material_index = intersect.material.as_ref().map_or(1.0, |material| material.refractive_index);
+
+    let (normal, n1, n2) = if ray_direction.dot(&intersect.normal) < 0.0 {
+        (intersect.normal, 1.0, material_index) // Del aire hacia el material
+    } else {
+        (-intersect.normal, material_index, 1.0) // Del material hacia el aire
+    };
+
+    match refract(ray_direction, &normal, n1, n2) {
+        Some(refracted_direction) => {
+            let cos_i = -ray_direction.dot(&normal);
+            let cos_t = -refracted_direction.dot(&normal);
+            let kr = fresnel(cos_i, cos_t, n1, n2);
+
+            let refract_origin = offset_origin(&intersect.point, &normal, &refracted_direction);
+            let refract_differential = differential.and_then(|d| d.bend(|dir| refract(dir, &normal, n1, n2)));
+            let color = cast_ray(&refract_origin, &refracted_direction, refract_differential, scene, lights, depth - 1).0;
+            (color, kr)
+        }
+        // Reflexión total interna: toda la luz se refleja
+        None => (Color::new(0, 0, 0), 1.0),
+    }
+}
 
-    // Calcular el ángulo de refracción usando la Ley de Snell
-    let cos_i = -ray_direction.dot(&normal);
-    let sin_t2 = (n1 / n2) * (n1 / n2) * (1.0 - cos_i * cos_i);
+// Ley de Snell. `normal` apunta hacia el lado de donde viene el rayo (índice `n1`).
+// Devuelve `None` si hay reflexión total interna.
+fn refract(incident: &Vec3, normal: &Vec3, n1: f32, n2: f32) -> Option<Vec3> {
+    let eta = n1 / n2;
+    let cos_i = -incident.dot(normal);
+    let sin_t2 = eta * eta * (1.0 - cos_i * cos_i);
 
     if sin_t2 > 1.0 {
-        // Reflexión total interna
-        return cast_ray(&intersect.point, &reflect(ray_direction, &normal), objects, light, depth - 1).0;
+        return None;
+    }
+
+    let cos_t = (1.0 - sin_t2).sqrt();
+    Some((eta * incident + (eta * cos_i - cos_t) * normal).normalize())
+}
+
+// Aproximación de Schlick para la reflectancia de Fresnel. Al salir hacia un medio menos denso
+// se usa el ángulo del rayo transmitido.
+fn fresnel(cos_i: f32, cos_t: f32, n1: f32, n2: f32) -> f32 {
+    let r0 = ((n1 - n2) / (n1 + n2)).powi(2);
+    let cos = if n1 > n2 { cos_t } else { cos_i };
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+}
+
+// Desplaza el origen de un rayo secundario hacia el lado de la superficie por el que sale,
+// para que no vuelva a chocar con la misma superficie
+fn offset_origin(point: &Vec3, normal: &Vec3, direction: &Vec3) -> Vec3 {
+    let bias = 1e-3;
+    if direction.dot(normal) < 0.0 {
+        point - normal * bias
     } else {
-        // Refracción
-        let cos_t = (1.0 - sin_t2).sqrt();
-        let refracted_direction = (n1 / n2) * ray_direction + (n1 / n2 * cos_i - cos_t) * normal;
-        return cast_ray(&intersect.point, &refracted_direction, objects, light, depth - 1).0;
-    }    
+        point + normal * bias
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::figures::Cube;
+    use crate::material::Material;
+    use crate::ray_intersect::Renderable;
+    use crate::texture::{Texel, Texture};
+
+    // Material sin textura: `get_diffuse` devuelve `diffuse`
+    fn plain(diffuse: Color, specular: f32, albedo: [f32; 4], refractive_index: f32) -> Arc<Material> {
+        Arc::new(Material::new(
+            diffuse,
+            specular,
+            albedo,
+            refractive_index,
+            [None, None, None, None, None, None],
+            false,
+        ))
+    }
+
+    fn cube(center: Vec3, material: &Arc<Material>) -> Box<dyn Renderable> {
+        Box::new(Cube { center, length: 1, material: material.clone() })
+    }
+
+    // Renderiza en un framebuffer de 4x4 y devuelve el píxel (2, 2), cuyo rayo primario sale
+    // de (0, 0, 5) exactamente en la dirección -Z,
+    // en color lineal, antes del mapeo de tonos
+    fn center_pixel(objects: Vec<Box<dyn Renderable>>, lights: &[Light]) -> Color {
+        let scene = Bvh::new(objects);
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0));
+
+        let mut framebuffer = Framebuffer::new(4, 4);
+        render(&mut framebuffer, &scene, &camera, lights, &RenderSettings::new(4, 1).unwrap());
+        framebuffer.get_hdr_buffer_mut()[2 * 4 + 2]
+    }
+
+    fn assert_close(actual: Color, expected: Color) {
+        let error = (actual.r() - expected.r()).abs()
+            + (actual.g() - expected.g()).abs()
+            + (actual.b() - expected.b()).abs();
+        assert!(error < 1e-4, "{} != {}", actual, expected);
+    }
+
+    // Luz que viaja hacia -Z, es decir, desde el lado de la cámara
+    fn frontal_light() -> Vec<Light> {
+        vec![Light::directional(Vec3::new(0.0, 0.0, -1.0), Color::rgb(1.0, 1.0, 1.0), 1.0)]
+    }
+
+    #[test]
+    fn diffuse_and_specular_are_both_applied() {
+        let material = plain(Color::rgb(0.8, 0.4, 0.2), 10.0, [0.5, 0.2, 0.0, 0.0], 1.0);
+
+        // ambiente:  0.08 * 0.3 + (0.8, 0.4, 0.2) * 0.2     = (0.184, 0.104, 0.064)
+        // difusa:    (0.8, 0.4, 0.2) * (n·l = 1) * albedo[0] 0.5 = (0.4, 0.2, 0.1)
+        // especular: 1.0 * (r·v = 1)^10 * albedo[1] 0.2         = (0.2, 0.2, 0.2)
+        let pixel = center_pixel(vec![cube(Vec3::zeros(), &material)], &frontal_light());
+        assert_close(pixel, Color::rgb(0.784, 0.504, 0.364));
+    }
+
+    #[test]
+    fn mirror_shows_only_what_it_reflects() {
+        let mirror = plain(Color::rgb(0.8, 0.4, 0.2), 10.0, [0.5, 0.2, 1.0, 0.0], 1.0);
+
+        // El rayo rebota de vuelta hacia la cámara y no toca nada: color de fondo (4, 12, 36).
+        // La componente local queda multiplicada por 1 - albedo[2] = 0.
+        let pixel = center_pixel(vec![cube(Vec3::zeros(), &mirror)], &frontal_light());
+        assert_close(pixel, Color::new(4, 12, 36));
+    }
+
+    #[test]
+    fn partial_reflection_mixes_local_and_reflected_color() {
+        let material = plain(Color::rgb(0.8, 0.4, 0.2), 10.0, [0.5, 0.2, 0.5, 0.0], 1.0);
+
+        // local (0.784, 0.504, 0.364) * 0.5 + fondo (4, 12, 36) * 0.5
+        let pixel = center_pixel(vec![cube(Vec3::zeros(), &material)], &frontal_light());
+        assert_close(pixel, Color::rgb(0.392, 0.252, 0.182) + Color::new(4, 12, 36) * 0.5);
+    }
+
+    #[test]
+    fn refraction_goes_through_both_faces_of_the_cube() {
+        // Con índice 1.0 el rayo atraviesa sin desviarse y Fresnel vale 0 a incidencia normal
+        let glass = plain(Color::rgb(1.0, 1.0, 1.0), 10.0, [0.0, 0.0, 0.0, 1.0], 1.0);
+        let wall = plain(Color::rgb(0.8, 0.4, 0.2), 10.0, [0.5, 0.2, 0.0, 0.0], 1.0);
+
+        // El vidrio bloquea los rayos de sombra, así que la pared detrás sólo recibe luz ambiental:
+        // 0.08 * 0.3 + (0.8, 0.4, 0.2) * 0.2 = (0.184, 0.104, 0.064)
+        let objects = vec![cube(Vec3::zeros(), &glass), cube(Vec3::new(0.0, 0.0, -2.0), &wall)];
+        let pixel = center_pixel(objects, &frontal_light());
+        assert_close(pixel, Color::rgb(0.184, 0.104, 0.064));
+    }
+
+    #[test]
+    fn thread_count_and_tile_size_do_not_change_the_image() {
+        let material = plain(Color::rgb(0.8, 0.4, 0.2), 10.0, [0.5, 0.2, 0.3, 0.0], 1.0);
+        let scene = Bvh::new(vec![
+            cube(Vec3::zeros(), &material),
+            cube(Vec3::new(1.5, 0.5, -1.0), &material),
+            cube(Vec3::new(-1.0, -1.0, 1.0), &material),
+        ]);
+        let camera = Camera::new(Vec3::new(2.0, 3.0, 6.0), Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0));
+        let (edge_u, edge_v) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let lights = vec![Light::rect(Vec3::new(0.0, 5.0, 2.0), edge_u, edge_v, Color::rgb(1.0, 1.0, 1.0), 2.0, 4)];
+
+        let render_with = |threads: usize, tile_size: usize| {
+            let mut settings = RenderSettings::new(tile_size, threads).unwrap();
+            settings.samples = 4;
+            settings.pattern = SamplePattern::Halton;
+            settings.filter = PixelFilter::Gaussian;
+            settings.seed = 11;
+
+            let mut framebuffer = Framebuffer::new(24, 17);
+            render(&mut framebuffer, &scene, &camera, &lights, &settings);
+            // Comparación bit a bit, no aproximada
+            let hdr = framebuffer.get_hdr_buffer();
+            hdr.iter().flat_map(|c| [c.r(), c.g(), c.b()]).map(f32::to_bits).collect::<Vec<_>>()
+        };
+
+        let serial = render_with(1, 17);
+        assert_eq!(serial, render_with(4, 1));
+        assert_eq!(serial, render_with(4, 5));
+        assert_eq!(serial, render_with(0, 8));
+    }
+
+    #[test]
+    fn single_regular_sample_hits_the_pixel_corner() {
+        for filter in PixelFilter::ALL {
+            let (dx, dy) = sample_offset(pixel_corner_sample(filter.radius()), filter.radius());
+            assert!((dx + 0.5).abs() < 1e-6 && (dy + 0.5).abs() < 1e-6, "{}: ({}, {})", filter.name(), dx, dy);
+        }
+    }
+
+    #[test]
+    fn masked_texels_let_rays_and_shadows_through() {
+        // Textura de un solo texel totalmente transparente: el cubo recortado no existe
+        let clear = Texel { color: Color::rgb(1.0, 0.0, 0.0), alpha: 0.0 };
+        let texture = Some(Arc::new(Texture::new(1, 1, vec![clear])));
+        let textures = std::array::from_fn(|_| texture.clone());
+        let mut leaf = Material::new(Color::rgb(1.0, 0.0, 0.0), 10.0, [0.5, 0.2, 0.0, 0.0], 1.0, textures, true);
+        leaf.alpha_mode = AlphaMode::Mask;
+        let wall = plain(Color::rgb(0.8, 0.4, 0.2), 10.0, [0.5, 0.2, 0.0, 0.0], 1.0);
+
+        // La pared recibe la luz entera, igual que sin el cubo delante
+        let objects = vec![cube(Vec3::zeros(), &Arc::new(leaf)), cube(Vec3::new(0.0, 0.0, -2.0), &wall)];
+        let pixel = center_pixel(objects, &frontal_light());
+        assert_close(pixel, Color::rgb(0.784, 0.504, 0.364));
+    }
+
+    #[test]
+    fn blended_surface_mixes_with_what_is_behind() {
+        // Sin especular, para que la cara trasera sólo aporte luz ambiental
+        let mut tint = Material::clone(&plain(Color::rgb(0.8, 0.4, 0.2), 10.0, [0.5, 0.0, 0.0, 0.0], 1.0));
+        tint.alpha_mode = AlphaMode::Blend;
+        tint.opacity = 0.5;
+
+        // Cara frontal: ambiente + difusa (0.584, 0.304, 0.164) * 0.5. Detrás, la cara trasera
+        // vista desde dentro no recibe luz directa (n·l < 0): ambiente (0.184, 0.104, 0.064) * 0.25.
+        // Por último, el fondo con lo que queda: 0.25.
+        let pixel = center_pixel(vec![cube(Vec3::zeros(), &Arc::new(tint))], &frontal_light());
+        let expected = Color::rgb(0.338, 0.178, 0.098) + Color::new(4, 12, 36) * 0.25;
+        assert_close(pixel, expected);
+    }
+
+    #[test]
+    fn refraction_through_a_slab_keeps_the_direction() {
+        let incident = Vec3::new(0.6, -0.8, 0.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        // Entrada aire → vidrio: sen θt = (1 / 1.5) * 0.6 = 0.4
+        let inside = refract(&incident, &up, 1.0, 1.5).unwrap();
+        assert!((inside.x - 0.4).abs() < 1e-5);
+        assert!((inside.y + 0.84f32.sqrt()).abs() < 1e-5);
+
+        // Salida vidrio → aire por la cara opuesta: vuelve a la dirección original
+        let outside = refract(&inside, &up, 1.5, 1.0).unwrap();
+        assert!((outside - incident).norm() < 1e-5);
+    }
+
+    #[test]
+    fn total_internal_reflection_past_the_critical_angle() {
+        // sen θi = 0.8 > 1 / 1.5: no hay rayo transmitido al salir del vidrio
+        let incident = Vec3::new(0.8, -0.6, 0.0);
+        assert!(refract(&incident, &Vec3::new(0.0, 1.0, 0.0), 1.5, 1.0).is_none());
+    }
+
+    #[test]
+    fn schlick_fresnel_at_normal_and_grazing_incidence() {
+        // r0 = ((1 - 1.5) / (1 + 1.5))^2 = 0.04
+        assert!((fresnel(1.0, 1.0, 1.0, 1.5) - 0.04).abs() < 1e-6);
+        assert!((fresnel(0.0, 0.0, 1.0, 1.5) - 1.0).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,322 @@
+// sampling.rs
+
+use nalgebra_glm::Vec3;
+
+// Generador pseudoaleatorio pequeño y determinista (xorshift64*), suficiente para muestreo
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // El estado nunca puede ser cero
+        Rng { state: mix(seed) | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Número uniforme en [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+// Mezcla de bits de splitmix64: convierte entradas parecidas en semillas muy distintas
+pub fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Semilla estable a partir de un punto, para que el ruido no cambie entre cuadros
+pub fn hash_point(point: &Vec3) -> u64 {
+    let x = mix(point.x.to_bits() as u64);
+    let y = mix(x ^ point.y.to_bits() as u64);
+    mix(y ^ point.z.to_bits() as u64)
+}
+
+// `count` muestras estratificadas en [0, 1)^2: una por celda de una cuadrícula, con jitter.
+// Cada celda recibe exactamente una muestra, así que ninguna zona del dominio queda sin cubrir.
+pub fn stratified(count: u32, rng: &mut Rng) -> Vec<(f32, f32)> {
+    let (columns, rows) = strata(count);
+
+    (0..count)
+        .map(|i| {
+            let column = i % columns;
+            let row = i / columns;
+            (
+                (column as f32 + rng.next_f32()) / columns as f32,
+                (row as f32 + rng.next_f32()) / rows as f32,
+            )
+        })
+        .collect()
+}
+
+// Cuadrícula de `columns * rows == count` celdas lo más cuadrada posible: el mayor divisor de
+// `count` que no pasa de su raíz da las filas. Con un número primo queda una sola fila de franjas.
+fn strata(count: u32) -> (u32, u32) {
+    let count = count.max(1);
+    let rows = (1..=(count as f32).sqrt() as u32).rev().find(|rows| count.is_multiple_of(*rows)).unwrap_or(1);
+    (count / rows, rows)
+}
+
+// Distribución de las muestras dentro de un píxel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplePattern {
+    // Cuadrícula fija, igual en todos los píxeles
+    Regular,
+    // Una muestra con jitter por celda de la cuadrícula
+    Stratified,
+    // Secuencia de Halton en bases 2 y 3, con una rotación aleatoria por píxel
+    Halton,
+    // Secuencia de Sobol (0, 2) con un scramble XOR por píxel
+    Sobol,
+}
+
+impl SamplePattern {
+    pub const ALL: [SamplePattern; 4] =
+        [SamplePattern::Regular, SamplePattern::Stratified, SamplePattern::Halton, SamplePattern::Sobol];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "regular" | "grid" => Some(SamplePattern::Regular),
+            "stratified" | "jitter" => Some(SamplePattern::Stratified),
+            "halton" => Some(SamplePattern::Halton),
+            "sobol" => Some(SamplePattern::Sobol),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplePattern::Regular => "regular",
+            SamplePattern::Stratified => "stratified",
+            SamplePattern::Halton => "halton",
+            SamplePattern::Sobol => "sobol",
+        }
+    }
+
+    // `count` posiciones en [0, 1)^2. La cuadrícula regular redondea `count` al cuadrado más
+    // cercano; el resto de patrones devuelven exactamente `count` muestras. `seed` decorrelaciona
+    // píxeles distintos y no afecta a la cuadrícula regular.
+    pub fn generate(&self, count: u32, seed: u64) -> Vec<(f32, f32)> {
+        let count = count.max(1);
+        let mut rng = Rng::new(seed);
+
+        match self {
+            SamplePattern::Regular => {
+                let side = (count as f32).sqrt().round().max(1.0) as u32;
+                let step = 1.0 / side as f32;
+                (0..side * side)
+                    .map(|i| ((i % side) as f32 * step + step / 2.0, (i / side) as f32 * step + step / 2.0))
+                    .collect()
+            }
+            SamplePattern::Stratified => stratified(count, &mut rng),
+            SamplePattern::Halton => {
+                // Rotación de Cranley-Patterson: conserva la distribución y evita que todos los
+                // píxeles usen exactamente los mismos puntos
+                let (shift_x, shift_y) = (rng.next_f32(), rng.next_f32());
+                (1..=count)
+                    .map(|i| ((radical_inverse(i, 2) + shift_x).fract(), (radical_inverse(i, 3) + shift_y).fract()))
+                    .collect()
+            }
+            SamplePattern::Sobol => {
+                let scramble_x = rng.next_u64() as u32;
+                let scramble_y = rng.next_u64() as u32;
+                (0..count)
+                    .map(|i| (to_unit(i.reverse_bits() ^ scramble_x), to_unit(sobol_second(i) ^ scramble_y)))
+                    .collect()
+            }
+        }
+    }
+}
+
+// Filtro de reconstrucción: pondera cada muestra según su distancia al centro del píxel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelFilter {
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali con B = C = 1/3
+    Mitchell,
+}
+
+impl PixelFilter {
+    pub const ALL: [PixelFilter; 4] = [PixelFilter::Box, PixelFilter::Tent, PixelFilter::Gaussian, PixelFilter::Mitchell];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "box" => Some(PixelFilter::Box),
+            "tent" | "triangle" => Some(PixelFilter::Tent),
+            "gaussian" => Some(PixelFilter::Gaussian),
+            "mitchell" => Some(PixelFilter::Mitchell),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PixelFilter::Box => "box",
+            PixelFilter::Tent => "tent",
+            PixelFilter::Gaussian => "gaussian",
+            PixelFilter::Mitchell => "mitchell",
+        }
+    }
+
+    // Radio del soporte del filtro en píxeles; las muestras se reparten por todo el soporte
+    pub fn radius(&self) -> f32 {
+        match self {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent => 1.0,
+            PixelFilter::Gaussian => 1.5,
+            PixelFilter::Mitchell => 2.0,
+        }
+    }
+
+    // Peso de una muestra desplazada `(dx, dy)` píxeles del centro. El filtro es separable.
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent => (1.0 - x).max(0.0),
+            PixelFilter::Gaussian => {
+                // σ = 0.5, desplazada para que llegue a cero en el borde del soporte
+                let gaussian = |x: f32| (-2.0 * x * x).exp();
+                (gaussian(x) - gaussian(self.radius())).max(0.0)
+            }
+            PixelFilter::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let x2 = x * x;
+                let x3 = x2 * x;
+                let value = if x < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+                } else if x < 2.0 {
+                    (-b - 6.0 * c) * x3 + (6.0 * b + 30.0 * c) * x2 + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+                } else {
+                    0.0
+                };
+                value / 6.0
+            }
+        }
+    }
+}
+
+// Inversa radical de `index` en la base dada (van der Corput)
+fn radical_inverse(mut index: u32, base: u32) -> f32 {
+    let inv_base = 1.0 / base as f32;
+    let mut factor = inv_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f32 * factor;
+        index /= base;
+        factor *= inv_base;
+    }
+    result
+}
+
+// Segunda dimensión de Sobol (polinomio x + 1) en punto fijo de 32 bits
+fn sobol_second(mut index: u32) -> u32 {
+    let mut direction = 1u32 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+fn to_unit(bits: u32) -> f32 {
+    // 24 bits de mantisa para quedar siempre por debajo de 1.0
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stratified_samples_cover_every_stratum_once() {
+        for count in [1, 2, 3, 5, 6, 8, 9, 12, 16, 17] {
+            let (columns, rows) = strata(count);
+            assert_eq!(columns * rows, count);
+
+            let mut hits = vec![0; count as usize];
+            for (x, y) in stratified(count, &mut Rng::new(count as u64)) {
+                let column = (x * columns as f32) as u32;
+                let row = (y * rows as f32) as u32;
+                hits[(row * columns + column) as usize] += 1;
+            }
+            assert!(hits.iter().all(|&h| h == 1), "count {}: {:?}", count, hits);
+        }
+
+        assert_eq!(strata(8), (4, 2));
+        assert_eq!(strata(16), (4, 4));
+    }
+
+    // Integral de `weight_1d` sobre su soporte, por el punto medio
+    fn integral(filter: PixelFilter) -> f32 {
+        let steps = 4000;
+        let radius = filter.radius();
+        let dx = 2.0 * radius / steps as f32;
+        (0..steps).map(|i| filter.weight_1d(-radius + (i as f32 + 0.5) * dx) * dx).sum()
+    }
+
+    #[test]
+    fn filters_are_normalized_and_vanish_outside_their_support() {
+        // Box, tent y Mitchell integran 1; la gaussiana desplazada integra algo menos, y el
+        // renderizador divide por la suma de pesos de todas formas
+        for filter in [PixelFilter::Box, PixelFilter::Tent, PixelFilter::Mitchell] {
+            assert!((integral(filter) - 1.0).abs() < 1e-3, "{}: {}", filter.name(), integral(filter));
+        }
+        assert!(integral(PixelFilter::Gaussian) > 0.5);
+
+        for filter in [PixelFilter::Tent, PixelFilter::Gaussian, PixelFilter::Mitchell] {
+            let radius = filter.radius();
+            assert!(filter.weight(radius, 0.0).abs() < 1e-6, "{}", filter.name());
+            assert_eq!(filter.weight(radius + 0.1, 0.0), 0.0, "{}", filter.name());
+        }
+    }
+
+    #[test]
+    fn mitchell_values_at_origin_and_support_edge() {
+        // Con B = C = 1/3: (6 - 2B) / 6 = 8/9 en el origen, por eje
+        let center = 8.0 / 9.0;
+        assert!((PixelFilter::Mitchell.weight(0.0, 0.0) - center * center).abs() < 1e-6);
+        // En |x| = 1 vale B / 6 = 1/18; el lóbulo negativo está entre 1 y 2
+        assert!((PixelFilter::Mitchell.weight(1.0, 0.0) - center / 18.0).abs() < 1e-6);
+        assert!(PixelFilter::Mitchell.weight(1.5, 0.0) < 0.0);
+        assert!(PixelFilter::Mitchell.weight(2.0, 0.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn halton_sequence_first_terms() {
+        let base2: Vec<f32> = (1..=4).map(|i| radical_inverse(i, 2)).collect();
+        let base3: Vec<f32> = (1..=4).map(|i| radical_inverse(i, 3)).collect();
+        assert_eq!(base2, [0.5, 0.25, 0.75, 0.125]);
+        for (actual, expected) in base3.iter().zip([1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0, 4.0 / 9.0]) {
+            assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn sobol_sequence_first_terms() {
+        let first: Vec<f32> = (0..8u32).map(|i| to_unit(i.reverse_bits())).collect();
+        let second: Vec<f32> = (0..8).map(|i| to_unit(sobol_second(i))).collect();
+        assert_eq!(first, [0.0, 0.5, 0.25, 0.75, 0.125, 0.625, 0.375, 0.875]);
+        assert_eq!(second, [0.0, 0.5, 0.75, 0.25, 0.625, 0.125, 0.375, 0.875]);
+    }
+}
@@ -0,0 +1,1178 @@
+// scene.rs
+//
+// Formato de escena de texto inspirado en TOML:
+//
+//   # comentario
+//   [camera]
+//   eye = [0.0, 0.0, 10.0]
+//   fov = 60              # grados; `fov_axis = "horizontal"` lo mide en horizontal
+//   projection = "isometric"  # o "perspective" (por defecto) / "orthographic", con `ortho_width`
+//                             # / "equirectangular" (2:1) / "cubemap" (3:2)
+//   aperture = 0.1        # radio de la lente; enfoca a `focus_distance` (por defecto, `center`)
+//
+//   [[material]]
+//   name = "dirt"
+//   diffuse = "#8B4513"
+//   texture = "../textures/dirt.png"
+//   filter = "nearest"    # o "bilinear"; `wrap` = "clamp" (por defecto), "repeat" o "mirror".
+//                         # A lo lejos se mezclan los mipmaps de la textura con cualquier filtro
+//   uv_scale = 4          # la textura se repite 4x4 veces en cada cara (1 por defecto); con
+//                         # `wrap` = "repeat" o "mirror" forma un mosaico
+//   alpha = "mask"        # "opaque" (por defecto), "mask" (recorte con `alpha_cutoff`, 0.5) o
+//                         # "blend" (translucidez; `opacity` multiplica el alfa de la textura)
+//                         # Las esferas no admiten "mask": no tienen coordenadas de textura
+//
+//   [[voxels]]
+//   material = "dirt"
+//   from = [0, -1, 0]
+//   to = [5, -1, 2]
+//
+// Animación opcional de la cámara, para `--animate`:
+//
+//   [animation]
+//   interpolation = "catmull-rom"   # o "bezier"
+//
+//   [[keyframe]]
+//   time = 0.0            # segundos; `center`, `up` y `fov` toman por defecto los de [camera]
+//   eye = [10.0, 4.0, 0.0]
+//
+// Las tablas `[camera]` y `[animation]` aparecen una vez; `[[material]]`, `[[light]]`, `[[cube]]`,
+// `[[sphere]]`, `[[voxels]]` y `[[keyframe]]` se pueden repetir. Las rutas de texturas son
+// relativas al archivo de escena.
+
+use nalgebra_glm::Vec3;
+use crate::animation::{CameraAnimation, Interpolation, Keyframe};
+use crate::camera::{Camera, FovAxis, ProjectionChoice};
+use crate::color::Color;
+use crate::figures::{Cube, Sphere};
+use crate::light::Light;
+use crate::material::{AlphaMode, Material};
+use crate::ray_intersect::Renderable;
+use crate::texture::{Texture, TextureCache, TextureFilter, WrapMode};
+use crate::voxel::{VoxelGrid, AIR};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Bloques que puede ocupar la caja que envuelve todas las regiones `[[voxels]]` (un byte cada uno)
+const MAX_VOXEL_CELLS: u64 = 1 << 26;
+// Rayos de sombra por punto de una luz de área; más no mejora la imagen y la vuelve lentísima
+const MAX_LIGHT_SAMPLES: i32 = 1024;
+
+pub struct Scene {
+    pub objects: Vec<Box<dyn Renderable>>,
+    pub camera: Camera,
+    pub lights: Vec<Light>,
+    pub animation: Option<CameraAnimation>,
+    pub textures: TextureCache, // Imágenes que usan los materiales, cada una cargada una vez
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "{}", err),
+            SceneError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(err: std::io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+fn parse_error<T>(line: usize, message: impl Into<String>) -> Result<T, SceneError> {
+    Err(SceneError::Parse { line, message: message.into() })
+}
+
+// Las imágenes se cargan a través de `textures`, que acaba en `Scene::textures`
+pub fn load_scene<P: AsRef<Path>>(path: P, textures: TextureCache) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_scene(&source, base_dir, textures)
+}
+
+pub fn parse_scene(source: &str, base_dir: &Path, mut textures: TextureCache) -> Result<Scene, SceneError> {
+    let sections = parse_sections(source)?;
+
+    // Primera pasada: materiales, para poder referirse a ellos por nombre en cualquier orden
+    let mut materials: HashMap<String, Arc<Material>> = HashMap::new();
+    for section in sections.iter().filter(|s| s.name == "material") {
+        let name = section.string("name")?;
+        if materials.contains_key(&name) {
+            return parse_error(section.line, format!("material '{}' is defined twice", name));
+        }
+        let material = build_material(section, base_dir, &mut textures)?;
+        materials.insert(name, Arc::new(material));
+    }
+
+    let find_material = |section: &Section| -> Result<Arc<Material>, SceneError> {
+        let name = section.string("material")?;
+        match materials.get(&name) {
+            Some(material) => Ok(material.clone()),
+            None => parse_error(section.entry_line("material"), format!("unknown material '{}'", name)),
+        }
+    };
+
+    let mut camera = None;
+    let mut animation_section = None;
+    let mut keyframe_sections = Vec::new();
+    let mut lights = Vec::new();
+    let mut objects: Vec<Box<dyn Renderable>> = Vec::new();
+    let mut voxel_regions = Vec::new();
+
+    for section in &sections {
+        match section.name.as_str() {
+            "material" => {}
+            "camera" => {
+                if camera.is_some() {
+                    return parse_error(section.line, "[camera] is defined twice");
+                }
+                camera = Some(build_camera(section)?);
+            }
+            "animation" => {
+                if animation_section.is_some() {
+                    return parse_error(section.line, "[animation] is defined twice");
+                }
+                animation_section = Some(section);
+            }
+            // Los keyframes se construyen al final: sus valores por defecto salen de [camera]
+            "keyframe" => keyframe_sections.push(section),
+            "light" => lights.push(build_light(section)?),
+            "cube" => {
+                section.expect_keys(&["center", "length", "material"])?;
+                let length = section.integer_or("length", 1)?;
+                let Some(length) = i16::try_from(length).ok().filter(|&length| length > 0) else {
+                    return parse_error(
+                        section.entry_line("length"),
+                        format!("'length' must be between 1 and {}, found {}", i16::MAX, length),
+                    );
+                };
+                objects.push(Box::new(Cube {
+                    center: section.vec3("center")?,
+                    length,
+                    material: find_material(section)?,
+                }));
+            }
+            "sphere" => {
+                section.expect_keys(&["center", "radius", "material"])?;
+                // Las esferas no tienen coordenadas UV, así que no hay texels que recortar
+                let material = find_material(section)?;
+                if material.alpha_mode == AlphaMode::Mask {
+                    return parse_error(
+                        section.entry_line("material"),
+                        format!(
+                            "material '{}' uses alpha = \"mask\", which spheres do not support",
+                            section.string("material")?
+                        ),
+                    );
+                }
+                objects.push(Box::new(Sphere {
+                    center: section.vec3("center")?,
+                    radius: section.number("radius")?,
+                    material: material.as_ref().clone(),
+                }));
+            }
+            "voxels" => {
+                section.expect_keys(&["material", "from", "to"])?;
+                let from = section.block("from")?;
+                let to = section.block("to")?;
+                // `material = "air"` vacía la región, útil para tallar huecos
+                let material = if section.string("material")? == "air" {
+                    None
+                } else {
+                    Some(find_material(section)?)
+                };
+                voxel_regions.push(VoxelRegion { from, to, material, line: section.line });
+            }
+            other => {
+                return parse_error(section.line, format!("unknown table '{}'", other));
+            }
+        }
+    }
+
+    if !voxel_regions.is_empty() {
+        objects.push(Box::new(build_voxel_grid(&voxel_regions)?));
+    }
+
+    let camera = camera.unwrap_or_else(|| {
+        Camera::new(Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+    });
+
+    let animation = build_animation(animation_section, &keyframe_sections, &camera)?;
+
+    Ok(Scene { objects, camera, lights, animation, textures })
+}
+
+// Animación de cámara a partir de `[animation]` y los `[[keyframe]]`, en el orden del archivo
+fn build_animation(
+    animation: Option<&Section>,
+    keyframes: &[&Section],
+    camera: &Camera,
+) -> Result<Option<CameraAnimation>, SceneError> {
+    let line = match (animation, keyframes.first()) {
+        (Some(section), _) => section.line,
+        (None, Some(section)) => section.line,
+        (None, None) => return Ok(None),
+    };
+
+    let mut interpolation = Interpolation::CatmullRom;
+    if let Some(section) = animation {
+        section.expect_keys(&["interpolation"])?;
+        if section.get("interpolation").is_some() {
+            let name = section.string("interpolation")?;
+            interpolation = Interpolation::from_name(&name).ok_or_else(|| SceneError::Parse {
+                line: section.entry_line("interpolation"),
+                message: format!("unknown interpolation '{}', expected \"catmull-rom\" or \"bezier\"", name),
+            })?;
+        }
+    }
+
+    let keyframes = keyframes
+        .iter()
+        .map(|section| {
+            section.expect_keys(&["time", "eye", "center", "up", "fov"])?;
+            Ok(Keyframe {
+                time: section.number("time")?,
+                eye: section.vec3("eye")?,
+                center: section.vec3_or("center", camera.center)?,
+                up: section.vec3_or("up", camera.up)?,
+                fov: fov_radians(section, camera.fov)?,
+            })
+        })
+        .collect::<Result<Vec<_>, SceneError>>()?;
+
+    CameraAnimation::new(keyframes, interpolation)
+        .map(Some)
+        .map_err(|message| SceneError::Parse { line, message })
+}
+
+// El campo de visión se escribe en grados; `default` está en radianes
+fn fov_radians(section: &Section, default: f32) -> Result<f32, SceneError> {
+    let fov = section.number_or("fov", default.to_degrees())?;
+    if fov <= 0.0 || fov >= 180.0 {
+        return parse_error(section.entry_line("fov"), "'fov' must be between 0 and 180 degrees");
+    }
+    Ok(fov.to_radians())
+}
+
+fn build_camera(section: &Section) -> Result<Camera, SceneError> {
+    section.expect_keys(&[
+        "eye",
+        "center",
+        "up",
+        "fov",
+        "fov_axis",
+        "near",
+        "projection",
+        "ortho_width",
+        "aperture",
+        "focus_distance",
+    ])?;
+
+    let mut camera = Camera::new(
+        section.vec3_or("eye", Vec3::new(0.0, 0.0, 10.0))?,
+        section.vec3_or("center", Vec3::new(0.0, 0.0, 0.0))?,
+        section.vec3_or("up", Vec3::new(0.0, 1.0, 0.0))?,
+    );
+
+    camera.fov = fov_radians(section, camera.fov)?;
+
+    if section.get("fov_axis").is_some() {
+        camera.fov_axis = match section.string("fov_axis")?.as_str() {
+            "vertical" => FovAxis::Vertical,
+            "horizontal" => FovAxis::Horizontal,
+            other => {
+                return parse_error(
+                    section.entry_line("fov_axis"),
+                    format!("unknown fov_axis '{}', expected \"vertical\" or \"horizontal\"", other),
+                )
+            }
+        };
+    }
+
+    camera.near = section.number_or("near", camera.near)?;
+    if camera.near < 0.0 {
+        return parse_error(section.entry_line("near"), "'near' must not be negative");
+    }
+
+    // Sin `ortho_width`, la vista ortográfica encuadra lo mismo que la perspectiva en una
+    // imagen cuadrada
+    if section.get("projection").is_some() {
+        let name = section.string("projection")?;
+        match ProjectionChoice::from_name(&name) {
+            Some(choice) => camera.apply_projection(choice, 1.0),
+            None => {
+                return parse_error(
+                    section.entry_line("projection"),
+                    format!(
+                        "unknown projection '{}', expected \"perspective\", \"orthographic\", \"isometric\", \
+                         \"equirectangular\" or \"cubemap\"",
+                        name
+                    ),
+                )
+            }
+        }
+    }
+
+    camera.ortho_width = section.number_or("ortho_width", camera.ortho_width)?;
+    if camera.ortho_width <= 0.0 {
+        return parse_error(section.entry_line("ortho_width"), "'ortho_width' must be positive");
+    }
+
+    // Profundidad de campo: por defecto enfoca `center`
+    camera.aperture = section.number_or("aperture", camera.aperture)?;
+    if camera.aperture < 0.0 {
+        return parse_error(section.entry_line("aperture"), "'aperture' must not be negative");
+    }
+    camera.focus_distance = section.number_or("focus_distance", camera.focus_distance)?;
+    if camera.focus_distance <= 0.0 {
+        return parse_error(section.entry_line("focus_distance"), "'focus_distance' must be positive");
+    }
+
+    Ok(camera)
+}
+
+struct VoxelRegion {
+    from: [i32; 3],
+    to: [i32; 3],
+    material: Option<Arc<Material>>, // `None` es aire
+    line: usize,
+}
+
+fn build_voxel_grid(regions: &[VoxelRegion]) -> Result<VoxelGrid, SceneError> {
+    let mut min = [i32::MAX; 3];
+    let mut max = [i32::MIN; 3];
+    for region in regions {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(region.from[axis]).min(region.to[axis]);
+            max[axis] = max[axis].max(region.from[axis]).max(region.to[axis]);
+        }
+
+        // Un error al escribir un límite puede pedir miles de millones de celdas: se avisa en
+        // la primera región que hace crecer la cuadrícula por encima del máximo
+        let cells = (0..3).try_fold(1u64, |cells, axis| {
+            let extent = (max[axis] as i64 - min[axis] as i64 + 1) as u64;
+            cells.checked_mul(extent)
+        });
+        if cells.is_none_or(|cells| cells > MAX_VOXEL_CELLS) {
+            return parse_error(
+                region.line,
+                format!("the voxel regions span more than {} blocks; check 'from' and 'to'", MAX_VOXEL_CELLS),
+            );
+        }
+    }
+
+    let mut grid = VoxelGrid::new(min, max);
+    let mut ids: Vec<(Arc<Material>, u8)> = Vec::new();
+
+    // Las regiones se aplican en orden: las posteriores sobrescriben a las anteriores
+    for region in regions {
+        let block = match &region.material {
+            None => AIR,
+            Some(material) => match ids.iter().find(|(m, _)| Arc::ptr_eq(m, material)) {
+                Some((_, id)) => *id,
+                None => {
+                    let id = grid.add_material(material.clone());
+                    ids.push((material.clone(), id));
+                    id
+                }
+            },
+        };
+        grid.fill(region.from, region.to, block);
+    }
+
+    Ok(grid)
+}
+
+// Dirección de una luz direccional o foco; un vector nulo no se puede normalizar
+fn light_direction(section: &Section) -> Result<Vec3, SceneError> {
+    let direction = section.vec3("direction")?;
+    if direction.magnitude() <= f32::EPSILON {
+        return parse_error(section.entry_line("direction"), "'direction' must not be a zero vector");
+    }
+    Ok(direction)
+}
+
+fn light_samples(section: &Section) -> Result<u32, SceneError> {
+    let samples = section.integer_or("samples", 16)?;
+    if !(1..=MAX_LIGHT_SAMPLES).contains(&samples) {
+        return parse_error(
+            section.entry_line("samples"),
+            format!("'samples' must be between 1 and {}, found {}", MAX_LIGHT_SAMPLES, samples),
+        );
+    }
+    Ok(samples as u32)
+}
+
+fn build_light(section: &Section) -> Result<Light, SceneError> {
+    let color = section.color_or("color", Color::new(255, 255, 255))?;
+    let intensity = section.number_or("intensity", 1.0)?;
+    let kind = match section.get("type") {
+        Some(entry) => entry.string()?,
+        None => "point".to_string(),
+    };
+
+    match kind.as_str() {
+        "point" => {
+            section.expect_keys(&["type", "position", "color", "intensity"])?;
+            Ok(Light::new(section.vec3("position")?, color, intensity))
+        }
+        "directional" => {
+            section.expect_keys(&["type", "direction", "color", "intensity"])?;
+            Ok(Light::directional(light_direction(section)?, color, intensity))
+        }
+        "spot" => {
+            section.expect_keys(&[
+                "type", "position", "direction", "inner_angle", "outer_angle", "falloff", "color", "intensity",
+            ])?;
+            // Los ángulos del archivo están en grados
+            let outer_angle = section.number_or("outer_angle", 30.0)?;
+            let inner_angle = section.number_or("inner_angle", outer_angle * 0.75)?;
+            Ok(Light::spot(
+                section.vec3("position")?,
+                light_direction(section)?,
+                inner_angle.to_radians(),
+                outer_angle.to_radians(),
+                section.number_or("falloff", 1.0)?,
+                color,
+                intensity,
+            ))
+        }
+        "rect" => {
+            section.expect_keys(&["type", "position", "edge_u", "edge_v", "samples", "color", "intensity"])?;
+            let edge_u = section.vec3("edge_u")?;
+            let edge_v = section.vec3("edge_v")?;
+            for (key, edge) in [("edge_u", edge_u), ("edge_v", edge_v)] {
+                if edge.magnitude() <= f32::EPSILON {
+                    return parse_error(section.entry_line(key), format!("'{}' must have a positive length", key));
+                }
+            }
+            if edge_u.cross(&edge_v).magnitude() <= f32::EPSILON {
+                return parse_error(section.entry_line("edge_v"), "'edge_u' and 'edge_v' must not be parallel");
+            }
+            Ok(Light::rect(section.vec3("position")?, edge_u, edge_v, color, intensity, light_samples(section)?))
+        }
+        "sphere" => {
+            section.expect_keys(&["type", "position", "radius", "samples", "color", "intensity"])?;
+            let radius = section.number("radius")?;
+            if !(radius > 0.0 && radius.is_finite()) {
+                return parse_error(section.entry_line("radius"), "'radius' must be positive");
+            }
+            Ok(Light::sphere(section.vec3("position")?, radius, color, intensity, light_samples(section)?))
+        }
+        other => parse_error(
+            section.entry_line("type"),
+            format!("unknown light type '{}', expected point, directional, spot, rect or sphere", other),
+        ),
+    }
+}
+
+fn build_material(section: &Section, base_dir: &Path, cache: &mut TextureCache) -> Result<Material, SceneError> {
+    section.expect_keys(&[
+        "name",
+        "diffuse",
+        "specular",
+        "albedo",
+        "refractive_index",
+        "texture",
+        "textures",
+        "filter",
+        "wrap",
+        "uv_scale",
+        "alpha",
+        "alpha_cutoff",
+        "opacity",
+    ])?;
+
+    let albedo = match section.get("albedo") {
+        Some(entry) => {
+            let values = entry.numbers()?;
+            if values.len() != 4 {
+                return parse_error(entry.line, "'albedo' must have 4 values");
+            }
+            [values[0], values[1], values[2], values[3]]
+        }
+        None => [1.0, 0.0, 0.0, 0.0],
+    };
+
+    // Orden de las caras: derecha, izquierda, arriba, abajo, frente, atrás
+    let mut textures: [Option<Arc<Texture>>; 6] = [None, None, None, None, None, None];
+    if let Some(entry) = section.get("texture") {
+        let texture = load_texture(&entry.string()?, base_dir, entry.line, cache)?;
+        textures = std::array::from_fn(|_| Some(texture.clone()));
+    }
+    if let Some(entry) = section.get("textures") {
+        let Value::Array(paths) = &entry.value else {
+            return parse_error(entry.line, "'textures' must be an array of 6 paths");
+        };
+        if paths.len() != 6 {
+            return parse_error(entry.line, "'textures' must be an array of 6 paths");
+        }
+        for (face, path) in paths.iter().enumerate() {
+            let Value::Str(path) = path else {
+                return parse_error(entry.line, "'textures' must be an array of 6 paths");
+            };
+            textures[face] = Some(load_texture(path, base_dir, entry.line, cache)?);
+        }
+    }
+
+    let has_texture = textures.iter().any(|texture| texture.is_some());
+
+    let mut material = Material::new(
+        section.color_or("diffuse", Color::new(255, 255, 255))?,
+        section.number_or("specular", 0.0)?,
+        albedo,
+        section.number_or("refractive_index", 1.0)?,
+        textures,
+        has_texture,
+    );
+
+    // Muestreo de las texturas: "nearest" (por defecto) o "bilinear"; "repeat" (por defecto),
+    // "clamp" o "mirror" para las coordenadas fuera de la textura
+    if let Some(entry) = section.get("filter") {
+        let name = entry.string()?;
+        material.texture_filter = TextureFilter::from_name(&name).ok_or_else(|| SceneError::Parse {
+            line: entry.line,
+            message: format!("unknown texture filter '{}', expected \"nearest\" or \"bilinear\"", name),
+        })?;
+    }
+    if let Some(entry) = section.get("wrap") {
+        let name = entry.string()?;
+        material.wrap_mode = WrapMode::from_name(&name).ok_or_else(|| SceneError::Parse {
+            line: entry.line,
+            message: format!("unknown wrap mode '{}', expected \"repeat\", \"clamp\" or \"mirror\"", name),
+        })?;
+    }
+    material.uv_scale = section.number_or("uv_scale", material.uv_scale)?;
+    if material.uv_scale <= 0.0 {
+        return parse_error(section.entry_line("uv_scale"), "'uv_scale' must be positive");
+    }
+
+    // Canal alfa de las texturas: "opaque" (por defecto) lo ignora, "mask" recorta los texels
+    // con alfa menor que `alpha_cutoff` y "blend" mezcla con lo que hay detrás
+    if let Some(entry) = section.get("alpha") {
+        let name = entry.string()?;
+        material.alpha_mode = AlphaMode::from_name(&name).ok_or_else(|| SceneError::Parse {
+            line: entry.line,
+            message: format!("unknown alpha mode '{}', expected \"opaque\", \"mask\" or \"blend\"", name),
+        })?;
+    }
+    material.alpha_cutoff = section.number_or("alpha_cutoff", material.alpha_cutoff)?;
+    if !(0.0..=1.0).contains(&material.alpha_cutoff) {
+        return parse_error(section.entry_line("alpha_cutoff"), "'alpha_cutoff' must be between 0 and 1");
+    }
+    material.opacity = section.number_or("opacity", material.opacity)?;
+    if !(0.0..=1.0).contains(&material.opacity) {
+        return parse_error(section.entry_line("opacity"), "'opacity' must be between 0 and 1");
+    }
+
+    Ok(material)
+}
+
+fn load_texture(
+    path: &str,
+    base_dir: &Path,
+    line: usize,
+    cache: &mut TextureCache,
+) -> Result<Arc<Texture>, SceneError> {
+    let full_path: PathBuf = base_dir.join(path);
+    cache.load(full_path).map_err(|err| SceneError::Parse { line, message: err.to_string() })
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(f32),
+    Str(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Str(_) => "a string",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+struct Entry {
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+impl Entry {
+    fn type_error<T>(&self, expected: &str) -> Result<T, SceneError> {
+        parse_error(
+            self.line,
+            format!("'{}' must be {}, found {}", self.key, expected, self.value.type_name()),
+        )
+    }
+
+    fn number(&self) -> Result<f32, SceneError> {
+        match self.value {
+            Value::Number(n) => Ok(n),
+            _ => self.type_error("a number"),
+        }
+    }
+
+    fn string(&self) -> Result<String, SceneError> {
+        match &self.value {
+            Value::Str(s) => Ok(s.clone()),
+            _ => self.type_error("a string"),
+        }
+    }
+
+    fn numbers(&self) -> Result<Vec<f32>, SceneError> {
+        let Value::Array(items) = &self.value else {
+            return self.type_error("an array of numbers");
+        };
+        items
+            .iter()
+            .map(|item| match item {
+                Value::Number(n) => Ok(*n),
+                _ => self.type_error("an array of numbers"),
+            })
+            .collect()
+    }
+
+    fn vec3(&self) -> Result<Vec3, SceneError> {
+        let values = self.numbers()?;
+        if values.len() != 3 {
+            return parse_error(self.line, format!("'{}' must have 3 values", self.key));
+        }
+        Ok(Vec3::new(values[0], values[1], values[2]))
+    }
+
+    fn color(&self) -> Result<Color, SceneError> {
+        match &self.value {
+            Value::Str(hex) => Color::from_hex_str(hex)
+                .map_err(|message| SceneError::Parse { line: self.line, message }),
+            Value::Array(_) => {
+                let values = self.numbers()?;
+                if values.len() != 3 {
+                    return parse_error(self.line, format!("'{}' must have 3 values", self.key));
+                }
+                Ok(Color::new(values[0] as i32, values[1] as i32, values[2] as i32))
+            }
+            _ => self.type_error("a \"#RRGGBB\" string or an [r, g, b] array"),
+        }
+    }
+}
+
+struct Section {
+    name: String,
+    line: usize,
+    entries: Vec<Entry>,
+}
+
+impl Section {
+    fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.key == key)
+    }
+
+    fn require(&self, key: &str) -> Result<&Entry, SceneError> {
+        match self.get(key) {
+            Some(entry) => Ok(entry),
+            None => parse_error(self.line, format!("[{}] is missing '{}'", self.name, key)),
+        }
+    }
+
+    fn entry_line(&self, key: &str) -> usize {
+        self.get(key).map_or(self.line, |entry| entry.line)
+    }
+
+    // Rechaza claves desconocidas para detectar errores de escritura
+    fn expect_keys(&self, allowed: &[&str]) -> Result<(), SceneError> {
+        for entry in &self.entries {
+            if !allowed.contains(&entry.key.as_str()) {
+                return parse_error(
+                    entry.line,
+                    format!("unknown key '{}' in [{}]", entry.key, self.name),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn string(&self, key: &str) -> Result<String, SceneError> {
+        self.require(key)?.string()
+    }
+
+    fn number(&self, key: &str) -> Result<f32, SceneError> {
+        self.require(key)?.number()
+    }
+
+    fn number_or(&self, key: &str, default: f32) -> Result<f32, SceneError> {
+        self.get(key).map_or(Ok(default), |entry| entry.number())
+    }
+
+    fn integer_or(&self, key: &str, default: i32) -> Result<i32, SceneError> {
+        match self.get(key) {
+            Some(entry) => {
+                let n = entry.number()?;
+                if n.fract() != 0.0 {
+                    return parse_error(entry.line, format!("'{}' must be an integer", key));
+                }
+                // `as` satura en silencio: 1e10 se convertiría en i32::MAX
+                if !(i32::MIN as f32..i32::MAX as f32).contains(&n) {
+                    return parse_error(entry.line, format!("'{}' is out of range, found {}", key, n));
+                }
+                Ok(n as i32)
+            }
+            None => Ok(default),
+        }
+    }
+
+    fn vec3(&self, key: &str) -> Result<Vec3, SceneError> {
+        self.require(key)?.vec3()
+    }
+
+    fn vec3_or(&self, key: &str, default: Vec3) -> Result<Vec3, SceneError> {
+        self.get(key).map_or(Ok(default), |entry| entry.vec3())
+    }
+
+    fn color_or(&self, key: &str, default: Color) -> Result<Color, SceneError> {
+        self.get(key).map_or(Ok(default), |entry| entry.color())
+    }
+
+    // Coordenadas enteras de un bloque
+    fn block(&self, key: &str) -> Result<[i32; 3], SceneError> {
+        let entry = self.require(key)?;
+        let position = entry.vec3()?;
+        if position.iter().any(|c| c.fract() != 0.0) {
+            return parse_error(entry.line, format!("'{}' must contain integer coordinates", key));
+        }
+        Ok([position.x as i32, position.y as i32, position.z as i32])
+    }
+}
+
+fn parse_sections(source: &str) -> Result<Vec<Section>, SceneError> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+    while let Some((line_number, raw_line)) = lines.next() {
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let name = if let Some(inner) = header.strip_prefix('[') {
+                inner.strip_suffix("]]")
+            } else {
+                header.strip_suffix(']')
+            };
+            let Some(name) = name.map(str::trim).filter(|name| !name.is_empty()) else {
+                return parse_error(line_number, format!("malformed table header '{}'", line));
+            };
+            sections.push(Section { name: name.to_string(), line: line_number, entries: Vec::new() });
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return parse_error(line_number, format!("expected 'key = value', found '{}'", line));
+        };
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return parse_error(line_number, format!("invalid key '{}'", key));
+        }
+
+        // Los arreglos pueden ocupar varias líneas hasta cerrar todos los corchetes
+        let mut value_text = value.trim().to_string();
+        while bracket_depth(&value_text) > 0 {
+            match lines.next() {
+                Some((_, next)) => {
+                    value_text.push(' ');
+                    value_text.push_str(strip_comment(next).trim());
+                }
+                None => return parse_error(line_number, format!("unclosed '[' in value of '{}'", key)),
+            }
+        }
+
+        let value = ValueParser::new(&value_text, line_number).parse()?;
+
+        let Some(section) = sections.last_mut() else {
+            return parse_error(line_number, format!("'{}' must be inside a [table]", key));
+        };
+        if section.get(key).is_some() {
+            return parse_error(line_number, format!("duplicate key '{}'", key));
+        }
+        section.entries.push(Entry { key: key.to_string(), value, line: line_number });
+    }
+
+    Ok(sections)
+}
+
+// Elimina el comentario final respetando los '#' dentro de cadenas (p. ej. colores "#RRGGBB")
+fn strip_comment(line: &str) -> &str {
+    let mut end = line.len();
+    for_each_unquoted(line, |i, c| {
+        if c == '#' {
+            end = i;
+            return false;
+        }
+        true
+    });
+    &line[..end]
+}
+
+fn bracket_depth(text: &str) -> i32 {
+    let mut depth = 0;
+    for_each_unquoted(text, |_, c| {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ => {}
+        }
+        true
+    });
+    depth
+}
+
+// Recorre los caracteres de `text` que quedan fuera de las cadenas, con su posición, hasta que
+// `visit` devuelve `false`. Una comilla escapada (\") no cierra la cadena.
+fn for_each_unquoted<F: FnMut(usize, char) -> bool>(text: &str, mut visit: F) {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                '\\' if !escaped => {
+                    escaped = true;
+                    continue;
+                }
+                '"' if !escaped => in_string = false,
+                _ => {}
+            }
+            escaped = false;
+        } else if c == '"' {
+            in_string = true;
+        } else if !visit(i, c) {
+            return;
+        }
+    }
+}
+
+struct ValueParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> ValueParser<'a> {
+    fn new(text: &'a str, line: usize) -> Self {
+        ValueParser { chars: text.chars().peekable(), line }
+    }
+
+    fn parse(mut self) -> Result<Value, SceneError> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        match self.chars.next() {
+            None => Ok(value),
+            Some(c) => parse_error(self.line, format!("unexpected '{}' after value", c)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, SceneError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('"') => self.string(),
+            Some('[') => self.array(),
+            Some(c) if c.is_ascii_digit() || *c == '-' || *c == '+' || *c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let word = self.word();
+                parse_error(self.line, format!("unexpected '{}' (strings need quotes)", word))
+            }
+            Some(c) => parse_error(self.line, format!("unexpected '{}'", c)),
+            None => parse_error(self.line, "missing value"),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '+')) {
+                break;
+            }
+            word.push(c);
+            self.chars.next();
+        }
+        word
+    }
+
+    fn number(&mut self) -> Result<Value, SceneError> {
+        let text = self.word();
+        text.parse::<f32>()
+            .map(Value::Number)
+            .or_else(|_| parse_error(self.line, format!("invalid number '{}'", text)))
+    }
+
+    fn string(&mut self) -> Result<Value, SceneError> {
+        self.chars.next(); // comilla de apertura
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(Value::Str(text)),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('n') => text.push('\n'),
+                    Some(c) => return parse_error(self.line, format!("unknown escape '\\{}'", c)),
+                    None => return parse_error(self.line, "unterminated string"),
+                },
+                Some(c) => text.push(c),
+                None => return parse_error(self.line, "unterminated string"),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, SceneError> {
+        self.chars.next(); // '['
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek() == Some(&']') {
+                self.chars.next();
+                return Ok(Value::Array(items));
+            }
+
+            items.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                Some(c) => return parse_error(self.line, format!("expected ',' or ']', found '{}'", c)),
+                None => return parse_error(self.line, "unclosed '['"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        parse_scene(source, Path::new(""), TextureCache::new())
+    }
+
+    // Línea del error de análisis, o falla si la escena carga o el error es de otro tipo
+    fn error_line(source: &str) -> usize {
+        match parse(source) {
+            Err(SceneError::Parse { line, .. }) => line,
+            Err(err) => panic!("expected a parse error, got {}", err),
+            Ok(_) => panic!("expected a parse error, the scene loaded"),
+        }
+    }
+
+    const MATERIAL: &str = "[[material]]\nname = \"stone\"\ndiffuse = \"#808080\"\n";
+
+    #[test]
+    fn every_table_kind_is_loaded() {
+        let source = format!(
+            "{}{}",
+            MATERIAL,
+            r#"
+[camera]
+eye = [1, 2, 3]
+center = [0, 0, 0]
+fov = 45
+
+[animation]
+interpolation = "catmull-rom"
+
+[[keyframe]]
+time = 0
+eye = [1, 2, 3]
+
+[[keyframe]]
+time = 2
+eye = [3, 2, 1]
+
+[[light]]
+position = [0, 5, 0]
+
+[[light]]
+type = "directional"
+direction = [0, -1, 0]
+
+[[light]]
+type = "spot"
+position = [0, 5, 0]
+direction = [0, -1, 0]
+
+[[light]]
+type = "rect"
+position = [0, 5, 0]
+edge_u = [1, 0, 0]
+edge_v = [0, 0, 1]
+
+[[light]]
+type = "sphere"
+position = [0, 5, 0]
+radius = 0.5
+
+[[cube]]
+center = [0, 0, 0]
+material = "stone"
+
+[[sphere]]
+center = [3, 0, 0]
+radius = 1
+material = "stone"
+
+[[voxels]]
+material = "stone"
+from = [-2, -1, -2]
+to = [2, -1, 2]
+"#
+        );
+
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.camera.eye, Vec3::new(1.0, 2.0, 3.0));
+        assert!((scene.camera.fov - 45f32.to_radians()).abs() < 1e-6);
+        assert_eq!(scene.lights.len(), 5);
+        // Cubo, esfera y una sola cuadrícula para todas las regiones de vóxeles
+        assert_eq!(scene.objects.len(), 3);
+        let animation = scene.animation.unwrap();
+        assert_eq!((animation.start(), animation.end()), (0.0, 2.0));
+    }
+
+    #[test]
+    fn multiline_arrays_and_comments() {
+        let source = r##"
+# Comentario de línea completa
+[[material]]   # comentario tras la cabecera
+name = "glass"  # el nombre
+diffuse = "#FFFFFF"  # el '#' del color no es un comentario
+albedo = [
+    0.1,  # difusa
+    0.2,
+    0.3, 0.4  # reflexión y transparencia
+]
+
+[[cube]]
+center = [0, 0, 0]
+material = "glass"
+"##;
+        let scene = parse(source).unwrap();
+        assert_eq!(scene.objects.len(), 1);
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_end_strings() {
+        assert_eq!(strip_comment(r##"name = "a\"#b" # comentario"##), r##"name = "a\"#b" "##);
+        assert_eq!(bracket_depth(r#"["a\"]","#), 1);
+        assert_eq!(bracket_depth(r#"["a\\", ["b"]]"#), 0);
+    }
+
+    #[test]
+    fn duplicate_material_reports_the_second_definition() {
+        let source = format!("{}\n[[material]]\nname = \"stone\"\n", MATERIAL);
+        assert_eq!(error_line(&source), 5);
+    }
+
+    #[test]
+    fn unknown_key_reports_its_line() {
+        let source = format!("{}\n[[cube]]\ncenter = [0, 0, 0]\nsize = 2\nmaterial = \"stone\"\n", MATERIAL);
+        assert_eq!(error_line(&source), 7);
+    }
+
+    #[test]
+    fn wrong_value_type_reports_its_line() {
+        let source = format!("{}\n[[sphere]]\ncenter = [0, 0, 0]\nradius = \"big\"\nmaterial = \"stone\"\n", MATERIAL);
+        assert_eq!(error_line(&source), 7);
+        assert_eq!(error_line("[camera]\neye = [0, 0]\n"), 2);
+    }
+
+    #[test]
+    fn zero_light_direction_is_rejected() {
+        assert_eq!(error_line("[[light]]\ntype = \"directional\"\ndirection = [0, 0, 0]\n"), 3);
+        assert_eq!(error_line("[[light]]\ntype = \"spot\"\nposition = [0, 1, 0]\ndirection = [0, 0, 0]\n"), 4);
+    }
+
+    #[test]
+    fn alpha_settings_out_of_range_report_their_line() {
+        assert_eq!(error_line(&format!("{}alpha = \"mask\"\nalpha_cutoff = 1.5\n", MATERIAL)), 5);
+        assert_eq!(error_line(&format!("{}alpha_cutoff = -0.1\n", MATERIAL)), 4);
+        assert_eq!(error_line(&format!("{}alpha = \"blend\"\nopacity = 2\n", MATERIAL)), 5);
+        assert!(parse(&format!("{}alpha = \"mask\"\nalpha_cutoff = 1\n", MATERIAL)).is_ok());
+    }
+
+    #[test]
+    fn area_light_settings_are_validated() {
+        let rect = "[[light]]\ntype = \"rect\"\nposition = [0, 4, 0]\n";
+        let sphere = "[[light]]\ntype = \"sphere\"\nposition = [0, 4, 0]\n";
+
+        assert!(parse(&format!("{}edge_u = [1, 0, 0]\nedge_v = [0, 0, 1]\nsamples = 1024\n", rect)).is_ok());
+        assert_eq!(error_line(&format!("{}edge_u = [0, 0, 0]\nedge_v = [0, 0, 1]\n", rect)), 4);
+        assert_eq!(error_line(&format!("{}edge_u = [1, 0, 0]\nedge_v = [2, 0, 0]\n", rect)), 5);
+        assert_eq!(error_line(&format!("{}edge_u = [1, 0, 0]\nedge_v = [0, 0, 1]\nsamples = 0\n", rect)), 6);
+        assert_eq!(error_line(&format!("{}edge_u = [1, 0, 0]\nedge_v = [0, 0, 1]\nsamples = 1025\n", rect)), 6);
+
+        assert!(parse(&format!("{}radius = 0.5\n", sphere)).is_ok());
+        assert_eq!(error_line(&format!("{}radius = 0\n", sphere)), 4);
+        assert_eq!(error_line(&format!("{}radius = -1\n", sphere)), 4);
+        assert_eq!(error_line(&format!("{}radius = 1\nsamples = 1e10\n", sphere)), 5);
+    }
+
+    #[test]
+    fn fov_out_of_range_reports_its_line() {
+        assert_eq!(error_line("[camera]\nfov = 180\n"), 2);
+        let keyframe = |fov: &str| format!("[[keyframe]]\ntime = 0\neye = [0, 0, 5]\nfov = {}\n", fov);
+        assert!(parse(&keyframe("90")).is_ok());
+        assert_eq!(error_line(&keyframe("0")), 4);
+        assert_eq!(error_line(&keyframe("270")), 4);
+    }
+
+    #[test]
+    fn uv_scale_must_be_positive() {
+        // El material se recupera del cubo que lo usa
+        let material = |settings: &str| {
+            let cube = "[[cube]]\ncenter = [0, 0, 0]\nmaterial = \"stone\"\n";
+            let scene = parse(&format!("{}{}{}", MATERIAL, settings, cube)).unwrap();
+            let hit = scene.objects[0].ray_intersect(&Vec3::new(0.0, 0.0, 5.0), &Vec3::new(0.0, 0.0, -1.0));
+            hit.material.unwrap()
+        };
+        let tiled = material("wrap = \"repeat\"\nuv_scale = 4\n");
+        assert_eq!((tiled.wrap_mode, tiled.uv_scale), (WrapMode::Repeat, 4.0));
+        let plain = material("");
+        assert_eq!((plain.wrap_mode, plain.uv_scale), (WrapMode::Clamp, 1.0));
+
+        assert_eq!(error_line(&format!("{}uv_scale = 0\n", MATERIAL)), 4);
+        assert_eq!(error_line(&format!("{}uv_scale = -2\n", MATERIAL)), 4);
+    }
+
+    #[test]
+    fn masked_material_on_a_sphere_is_rejected() {
+        let sphere = "[[sphere]]\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"stone\"\n";
+        assert!(parse(&format!("{}alpha = \"blend\"\n{}", MATERIAL, sphere)).is_ok());
+        assert_eq!(error_line(&format!("{}alpha = \"mask\"\n{}", MATERIAL, sphere)), 8);
+    }
+
+    #[test]
+    fn unclosed_array_reports_where_it_starts() {
+        assert_eq!(error_line("[camera]\n\neye = [0,\n  0,\n"), 3);
+    }
+}
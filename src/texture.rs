@@ -1,39 +1,533 @@
 // texture.rs
 
-use image::{DynamicImage, GenericImageView};
 use crate::Color;
+use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+// Lado en texels de la textura de sustitución, y de cada casilla del tablero
+const MISSING_SIZE: usize = 8;
+const MISSING_SQUARE: usize = 4;
+
+#[derive(Debug)]
+pub enum TextureError {
+    // No se pudo leer el archivo (no existe, sin permisos, ...)
+    Io { path: PathBuf, source: std::io::Error },
+    // El archivo existe pero la imagen está dañada o no se puede decodificar
+    Decode { path: PathBuf, source: image::ImageError },
+    // La extensión o el contenido no corresponden a ningún formato soportado
+    UnsupportedFormat { path: PathBuf, source: image::ImageError },
+}
+
+impl TextureError {
+    fn from_image(path: &Path, err: image::ImageError) -> Self {
+        let path = path.to_path_buf();
+        match err {
+            image::ImageError::IoError(source) => TextureError::Io { path, source },
+            image::ImageError::Unsupported(_) => TextureError::UnsupportedFormat { path, source: err },
+            source => TextureError::Decode { path, source },
+        }
+    }
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io { path, source } => {
+                write!(f, "cannot read texture '{}': {}", path.display(), source)
+            }
+            TextureError::Decode { path, source } => {
+                write!(f, "cannot decode texture '{}': {}", path.display(), source)
+            }
+            TextureError::UnsupportedFormat { path, source } => {
+                write!(f, "unsupported texture format '{}': {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io { source, .. } => Some(source),
+            TextureError::Decode { source, .. } | TextureError::UnsupportedFormat { source, .. } => Some(source),
+        }
+    }
+}
+
+// Cómo se obtiene el color entre texels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFilter {
+    // El texel más cercano: bordes nítidos, ideal para bloques pixelados
+    Nearest,
+    // Interpolación entre los cuatro texels más cercanos
+    Bilinear,
+}
+
+impl TextureFilter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "nearest" => Some(TextureFilter::Nearest),
+            "bilinear" | "linear" => Some(TextureFilter::Bilinear),
+            _ => None,
+        }
+    }
+}
+
+// Qué se hace con las coordenadas UV fuera de [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    // La textura se repite como un mosaico
+    Repeat,
+    // Se usa el texel del borde
+    Clamp,
+    // La textura se repite reflejada en cada copia, sin costuras
+    Mirror,
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "repeat" => Some(WrapMode::Repeat),
+            "clamp" => Some(WrapMode::Clamp),
+            "mirror" => Some(WrapMode::Mirror),
+            _ => None,
+        }
+    }
+
+    // Índice válido en `0..size` para el texel `index`, que puede caer fuera de la textura
+    fn apply(&self, index: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => index.rem_euclid(size),
+            WrapMode::Clamp => index.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let period = index.rem_euclid(2 * size);
+                if period < size { period } else { 2 * size - 1 - period }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+// Color lineal y opacidad de un punto de la textura
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Texel {
+    pub color: Color,
+    pub alpha: f32, // 0 transparente, 1 opaco
+}
+
+impl Texel {
+    pub fn opaque(color: Color) -> Self {
+        Texel { color, alpha: 1.0 }
+    }
+
+    fn lerp(&self, other: &Texel, t: f32) -> Texel {
+        Texel {
+            color: self.color.lerp(&other.color, t),
+            alpha: self.alpha + (other.alpha - self.alpha) * t,
+        }
+    }
+
+    // RGBA de 8 bits, con el color en sRGB, como se guarda en `MipLevel`
+    fn to_srgba8(self) -> [u8; 4] {
+        let [r, g, b] = self.color.to_srgb8();
+        [r, g, b, (self.alpha.clamp(0.0, 1.0) * 255.0).round() as u8]
+    }
+
+    fn from_srgba8([r, g, b, a]: [u8; 4]) -> Texel {
+        Texel { color: Color::new(r as i32, g as i32, b as i32), alpha: a as f32 / 255.0 }
+    }
+}
+
+// Un nivel de la cadena de mipmaps. Los texels se guardan como en la imagen, RGBA de 8 bits en
+// sRGB (4 bytes en lugar de los 16 de `Texel`), y se pasan a color lineal al leerlos.
+#[derive(Debug, Clone)]
+struct MipLevel {
+    data: Vec<[u8; 4]>,
+    width: usize,
+    height: usize,
+}
+
+impl MipLevel {
+    fn get_texel(&self, x: usize, y: usize) -> Texel {
+        Texel::from_srgba8(self.data[y * self.width + x])
+    }
+
+    // Nivel siguiente: la mitad de tamaño, cada texel es el promedio de un bloque de 2x2. En
+    // dimensiones impares el último bloque repite el texel del borde. El color se pondera por
+    // la opacidad para que los texels transparentes no oscurezcan los bordes de los recortes.
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut data = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let texel = |dx: usize, dy: usize| {
+                    let xi = (x * 2 + dx).min(self.width - 1);
+                    let yi = (y * 2 + dy).min(self.height - 1);
+                    self.get_texel(xi, yi)
+                };
+                let block = [texel(0, 0), texel(1, 0), texel(0, 1), texel(1, 1)];
+
+                let alpha: f32 = block.iter().map(|texel| texel.alpha).sum();
+                let color = if alpha > 0.0 {
+                    block.iter().fold(Color::black(), |sum, texel| sum + texel.color * texel.alpha) * (1.0 / alpha)
+                } else {
+                    block.iter().fold(Color::black(), |sum, texel| sum + texel.color) * 0.25
+                };
+                data.push(Texel { color, alpha: alpha * 0.25 }.to_srgba8());
+            }
+        }
+
+        MipLevel { data, width, height }
+    }
+
+    fn sample(&self, u: f32, v: f32, filter: TextureFilter, wrap: WrapMode) -> Texel {
+        let x = u * self.width as f32;
+        let y = v * self.height as f32;
+
+        match filter {
+            TextureFilter::Nearest => {
+                let x = wrap.apply(x.floor() as i64, self.width);
+                let y = wrap.apply(y.floor() as i64, self.height);
+                self.get_texel(x, y)
+            }
+            TextureFilter::Bilinear => {
+                // Los centros de los texels están en coordenadas enteras + 0.5
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+
+                let texel = |dx: i64, dy: i64| {
+                    let xi = wrap.apply(x0 as i64 + dx, self.width);
+                    let yi = wrap.apply(y0 as i64 + dy, self.height);
+                    self.get_texel(xi, yi)
+                };
+
+                let top = texel(0, 0).lerp(&texel(1, 0), tx);
+                let bottom = texel(0, 1).lerp(&texel(1, 1), tx);
+                top.lerp(&bottom, ty)
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Texture {
-    pub data: Arc<Vec<Color>>,
     pub width: usize,
     pub height: usize,
+    // Cadena de mipmaps: el nivel 0 es la imagen original y el último mide 1x1
+    levels: Vec<MipLevel>,
 }
 
 impl Texture {
-    pub fn get_color(&self, x: usize, y: usize) -> Color {
-        self.data[y * self.width + x]
+    // Crea la textura a partir de texels en color lineal, fila por fila desde arriba, y calcula
+    // sus mipmaps
+    pub fn new(width: usize, height: usize, data: Vec<Texel>) -> Self {
+        Texture::from_srgba8(width, height, data.into_iter().map(Texel::to_srgba8).collect())
+    }
+
+    // Igual que `new`, con texels RGBA de 8 bits en sRGB como los de un archivo de imagen
+    fn from_srgba8(width: usize, height: usize, data: Vec<[u8; 4]>) -> Self {
+        let mut levels = vec![MipLevel { data, width, height }];
+        while levels.last().is_some_and(|level| level.width > 1 || level.height > 1) {
+            let next = levels[levels.len() - 1].downsample();
+            levels.push(next);
+        }
+
+        Texture { width, height, levels }
     }
 
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Arc<Self> {
-        let img = image::open(path).expect("Failed to load texture");
-        let img = img.to_rgb8();
+    // Color y opacidad en las coordenadas `(u, v)`, con `(0, 0)` en la esquina superior izquierda y
+    // `(1, 1)` en la inferior derecha. `footprint` es el tamaño en UV de la zona que cubre el
+    // rayo; cuando abarca varios texels se mezclan los dos mipmaps más cercanos (filtrado
+    // trilineal) en lugar de muestrear texels sueltos, que parpadean a lo lejos.
+    pub fn sample(&self, u: f32, v: f32, footprint: f32, filter: TextureFilter, wrap: WrapMode) -> Texel {
+        let texels = footprint * self.width.max(self.height) as f32;
+        let max_level = (self.levels.len() - 1) as f32;
+        let lod = if texels > 1.0 { texels.log2().min(max_level) } else { 0.0 };
+
+        let level = lod.floor() as usize;
+        let texel = self.levels[level].sample(u, v, filter, wrap);
+        let t = lod - level as f32;
+        if t <= 0.0 {
+            return texel;
+        }
+        texel.lerp(&self.levels[level + 1].sample(u, v, filter, wrap), t)
+    }
+
+    // Bytes que ocupan los texels de todos los mipmaps
+    pub fn memory_size(&self) -> usize {
+        self.levels.iter().map(|level| level.data.len() * std::mem::size_of::<[u8; 4]>()).sum()
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Arc<Self>, TextureError> {
+        let path = path.as_ref();
+        let img = image::open(path).map_err(|err| TextureError::from_image(path, err))?;
+        let img = img.to_rgba8();
         let (width, height) = img.dimensions();
-        
-        // Primero, crea un Vec<Color>
-        let data: Vec<Color> = img
-            .pixels()
-            .map(|p| Color::new(p[0] as i32, p[1] as i32, p[2] as i32))  // Conversión de u8 a i32
-            .collect(); // Esto devuelve un Vec<Color>
-        
-        // Luego, convierte el Vec<Color> a un Arc<Vec<Color>>
-        Arc::new(Texture {
-            data: Arc::new(data),
-            width: width as usize,
-            height: height as usize,
-        })
-    }
-    
+
+        // Los texels se guardan tal cual; las imágenes sin canal alfa quedan opacas
+        let data = img.pixels().map(|p| p.0).collect();
+
+        Ok(Arc::new(Texture::from_srgba8(width as usize, height as usize, data)))
+    }
+
+    // Tablero magenta y negro que sustituye a las texturas que no se pudieron cargar: imposible
+    // de confundir con una textura de verdad
+    pub fn missing() -> Self {
+        let magenta = Color::new(255, 0, 255);
+        let data = (0..MISSING_SIZE * MISSING_SIZE)
+            .map(|index| {
+                let (x, y) = (index % MISSING_SIZE, index / MISSING_SIZE);
+                let color = if (x / MISSING_SQUARE + y / MISSING_SQUARE).is_multiple_of(2) { magenta } else { Color::black() };
+                Texel::opaque(color)
+            })
+            .collect();
+        Texture::new(MISSING_SIZE, MISSING_SIZE, data)
+    }
+}
+
+// Texturas ya cargadas, por ruta canónica: una imagen que usan varias caras o varios materiales
+// se decodifica una sola vez y todos comparten el mismo `Arc<Texture>`
+#[derive(Default)]
+pub struct TextureCache {
+    textures: HashMap<PathBuf, Arc<Texture>>,
+    // Si está activo, las texturas que fallan se sustituyen por `Texture::missing` con un aviso
+    // en lugar de devolver el error
+    pub substitute_missing: bool,
+}
+
+impl TextureCache {
+    pub fn new() -> Self {
+        TextureCache::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<Arc<Texture>, TextureError> {
+        // "a/../b.png" y "b.png" son la misma imagen; si la ruta no se puede resolver se usa tal cual
+        let path = path.as_ref();
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+
+        let texture = match Texture::load_from_file(&key) {
+            Ok(texture) => texture,
+            Err(err) if self.substitute_missing => {
+                eprintln!("warning: {}; using a placeholder texture", err);
+                Arc::new(Texture::missing())
+            }
+            Err(err) => return Err(err),
+        };
+        // También se guarda el sustituto, para avisar una sola vez por archivo
+        self.textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+
+    // Número de imágenes distintas cargadas
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    // Bytes que ocupan todas las texturas, mipmaps incluidos
+    pub fn memory_usage(&self) -> usize {
+        self.textures.values().map(|texture| texture.memory_size()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(size: usize) -> Texture {
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        let data = (0..size * size)
+            .map(|index| Texel::opaque(if (index % size + index / size).is_multiple_of(2) { white } else { Color::black() }))
+            .collect();
+        Texture::new(size, size, data)
+    }
+
+    // Gris lineal del nivel `index` de `graded_levels`
+    fn level_gray(index: usize) -> f32 {
+        Color::new(index as i32 * 60, 0, 0).r()
+    }
+
+    // Textura de 8x8 cuyos niveles son de un gris distinto cada uno, para saber de cuál sale
+    // cada muestra
+    fn graded_levels() -> Texture {
+        let mut texture = checkerboard(8);
+        for (index, level) in texture.levels.iter_mut().enumerate() {
+            let gray = index as u8 * 60;
+            level.data.fill([gray, gray, gray, 255]);
+        }
+        texture
+    }
+
+    fn gray(texel: Texel) -> f32 {
+        texel.color.r()
+    }
+
+    // Directorio temporal propio de cada prueba, vacío al empezar
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("texture-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn equivalent_paths_share_one_texture() {
+        let dir = temp_dir("cache");
+        std::fs::create_dir(dir.join("a")).unwrap();
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([10, 20, 30, 255])).save(dir.join("b.png")).unwrap();
+
+        let mut cache = TextureCache::new();
+        let first = cache.load(dir.join("a/../b.png")).unwrap();
+        let second = cache.load(dir.join("b.png")).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(cache.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_errors_keep_their_cause() {
+        use std::error::Error;
+
+        let dir = temp_dir("errors");
+        match Texture::load_from_file(dir.join("nonexistent.png")) {
+            Err(err @ TextureError::Io { .. }) => assert!(err.source().unwrap().is::<std::io::Error>()),
+            other => panic!("expected an I/O error, got {:?}", other),
+        }
+
+        std::fs::write(dir.join("garbage.png"), b"not a png at all").unwrap();
+        match Texture::load_from_file(dir.join("garbage.png")) {
+            Err(err @ TextureError::Decode { .. }) => assert!(err.source().unwrap().is::<image::ImageError>()),
+            other => panic!("expected a decoding error, got {:?}", other),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_texture_is_replaced_once_by_the_checker() {
+        let dir = temp_dir("missing");
+        let path = dir.join("nonexistent.png");
+
+        let mut cache = TextureCache::new();
+        assert!(matches!(cache.load(&path), Err(TextureError::Io { .. })));
+        assert!(cache.is_empty());
+
+        cache.substitute_missing = true;
+        let first = cache.load(&path).unwrap();
+        assert_eq!((first.width, first.height), (MISSING_SIZE, MISSING_SIZE));
+        assert_eq!(first.levels[0].data, Texture::missing().levels[0].data);
+
+        let second = cache.load(&path).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(cache.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn wrap_modes_map_indices_into_the_texture() {
+        // Índices de texel de una textura de 4 de ancho, desde dos copias a la izquierda
+        let indices = |wrap: WrapMode| (-8..=8).map(|index| wrap.apply(index, 4)).collect::<Vec<_>>();
+        assert_eq!(indices(WrapMode::Repeat), [0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0]);
+        assert_eq!(indices(WrapMode::Clamp), [0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3, 3]);
+        assert_eq!(indices(WrapMode::Mirror), [0, 1, 2, 3, 3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0, 0]);
+    }
+
+    // Textura de 2x1: negro a la izquierda y blanco a la derecha
+    fn black_and_white() -> Texture {
+        Texture::new(2, 1, vec![Texel::opaque(Color::black()), Texel::opaque(Color::rgb(1.0, 1.0, 1.0))])
+    }
+
+    #[test]
+    fn nearest_sampling_at_and_past_the_edges() {
+        let texture = black_and_white();
+        let sample = |u: f32, wrap: WrapMode| gray(texture.sample(u, 0.5, 0.0, TextureFilter::Nearest, wrap));
+
+        // u = 1 es el borde derecho: con Clamp sigue en el último texel
+        assert_eq!(sample(1.0, WrapMode::Clamp), 1.0);
+        assert_eq!(sample(1.0, WrapMode::Repeat), 0.0);
+        assert_eq!(sample(1.0, WrapMode::Mirror), 1.0);
+
+        // Fuera de [0, 1]
+        assert_eq!(sample(1.3, WrapMode::Clamp), 1.0);
+        assert_eq!(sample(1.3, WrapMode::Repeat), 0.0);
+        assert_eq!(sample(1.3, WrapMode::Mirror), 1.0);
+        assert_eq!(sample(-0.2, WrapMode::Clamp), 0.0);
+        assert_eq!(sample(-0.2, WrapMode::Repeat), 1.0);
+        assert_eq!(sample(-0.2, WrapMode::Mirror), 0.0);
+    }
+
+    #[test]
+    fn bilinear_interpolates_between_texel_centers() {
+        let texture = black_and_white();
+        let sample = |u: f32, wrap: WrapMode| gray(texture.sample(u, 0.5, 0.0, TextureFilter::Bilinear, wrap));
+
+        // Los centros están en u = 0.25 y 0.75; entre ellos el gris crece linealmente
+        assert_eq!(sample(0.25, WrapMode::Clamp), 0.0);
+        assert_eq!(sample(0.75, WrapMode::Clamp), 1.0);
+        assert!((sample(0.5, WrapMode::Clamp) - 0.5).abs() < 1e-6);
+        assert!((sample(0.375, WrapMode::Clamp) - 0.25).abs() < 1e-6);
+
+        // Fuera de los centros, Clamp repite el texel del borde y Repeat mezcla con el otro lado
+        assert_eq!(sample(1.0, WrapMode::Clamp), 1.0);
+        assert!((sample(1.0, WrapMode::Repeat) - 0.5).abs() < 1e-6);
+        assert!((sample(0.0, WrapMode::Repeat) - 0.5).abs() < 1e-6);
+        assert_eq!(sample(0.0, WrapMode::Mirror), 0.0);
+
+        // En vertical, con una sola fila, no cambia nada
+        let texel = texture.sample(0.5, 0.9, 0.0, TextureFilter::Bilinear, WrapMode::Repeat);
+        assert!((gray(texel) - 0.5).abs() < 1e-6 && texel.alpha == 1.0);
+    }
+
+    #[test]
+    fn footprint_selects_and_blends_mip_levels() {
+        let texture = graded_levels();
+        assert_eq!(texture.levels.len(), 4);
+        let sample = |footprint: f32| gray(texture.sample(0.3, 0.6, footprint, TextureFilter::Bilinear, WrapMode::Repeat));
+
+        // Sin footprint, o uno de un texel o menos, se usa la imagen original
+        assert_eq!(sample(0.0), 0.0);
+        assert_eq!(sample(1.0 / 8.0), 0.0);
+        assert_eq!(sample(0.5 / 8.0), 0.0);
+
+        // Dos texels justos caen en el nivel 1; tres, entre el 1 y el 2
+        assert!((sample(2.0 / 8.0) - level_gray(1)).abs() < 1e-5);
+        let t = 3.0f32.log2() - 1.0;
+        let blend = level_gray(1) + (level_gray(2) - level_gray(1)) * t;
+        assert!((sample(3.0 / 8.0) - blend).abs() < 1e-5);
+
+        // Más allá del último nivel se queda en el de 1x1
+        assert!((sample(4.0) - level_gray(3)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn checkerboard_top_level_averages_to_gray() {
+        for size in [2, 4, 16] {
+            let texture = checkerboard(size);
+            let top = texture.levels.last().unwrap();
+            assert_eq!((top.width, top.height), (1, 1));
+
+            // El promedio se guarda en 8 bits sRGB: 0.5 lineal queda en 188, que es 0.503
+            let texel = top.get_texel(0, 0);
+            for channel in [texel.color.r(), texel.color.g(), texel.color.b()] {
+                assert!((channel - 0.5).abs() < 0.005, "size {}: {:?}", size, texel);
+            }
+            assert_eq!(texel.alpha, 1.0);
+            assert_eq!(texture.sample(0.5, 0.5, 1.0, TextureFilter::Nearest, WrapMode::Repeat), texel);
+        }
+    }
 }
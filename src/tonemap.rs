@@ -0,0 +1,119 @@
+// tonemap.rs
+
+use crate::color::Color;
+
+// Operadores que llevan el color HDR lineal al rango [0, 1] de la pantalla
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapOperator {
+    // Recorte directo: idéntico al resultado de siempre para escenas sin sobreexposición
+    Clamp,
+    // c / (1 + c)
+    Reinhard,
+    // Curva fílmica ACES (aproximación de Krzysztof Narkowicz)
+    Aces,
+}
+
+impl ToneMapOperator {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "clamp" | "none" => Some(ToneMapOperator::Clamp),
+            "reinhard" => Some(ToneMapOperator::Reinhard),
+            "aces" | "filmic" => Some(ToneMapOperator::Aces),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    pub exposure: f32, // En pasos (EV): cada unidad duplica el brillo
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapOperator, exposure: f32) -> Self {
+        ToneMapping { operator, exposure }
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        let exposed = color * 2.0f32.powf(self.exposure);
+
+        match self.operator {
+            ToneMapOperator::Clamp => exposed,
+            ToneMapOperator::Reinhard => exposed.map(|c| c / (1.0 + c)),
+            ToneMapOperator::Aces => exposed.map(|c| {
+                let c = c.max(0.0);
+                (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+            }),
+        }
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping::new(ToneMapOperator::Clamp, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f32) -> Color {
+        Color::rgb(value, value, value)
+    }
+
+    fn assert_gray(color: Color, expected: f32) {
+        for channel in [color.r(), color.g(), color.b()] {
+            assert!((channel - expected).abs() < 1e-5, "{} != {}", color, expected);
+        }
+    }
+
+    #[test]
+    fn operators_at_known_values() {
+        let clamp = ToneMapping::default();
+        assert_gray(clamp.apply(gray(0.3)), 0.3);
+        assert_gray(clamp.apply(gray(4.0)), 4.0); // El recorte lo hace `to_hex`
+
+        let reinhard = ToneMapping::new(ToneMapOperator::Reinhard, 0.0);
+        assert_gray(reinhard.apply(gray(0.0)), 0.0);
+        assert_gray(reinhard.apply(gray(1.0)), 0.5);
+        assert_gray(reinhard.apply(gray(3.0)), 0.75);
+
+        // (1 * 2.54) / (1 * 3.02 + 0.14); tiende a 2.51 / 2.43 y no devuelve negativos
+        let aces = ToneMapping::new(ToneMapOperator::Aces, 0.0);
+        assert_gray(aces.apply(gray(0.0)), 0.0);
+        assert_gray(aces.apply(gray(1.0)), 2.54 / 3.16);
+        assert!((aces.apply(gray(1e6)).r() - 2.51 / 2.43).abs() < 1e-4);
+        assert_gray(aces.apply(gray(-1.0)), 0.0);
+    }
+
+    #[test]
+    fn exposure_scales_by_powers_of_two() {
+        assert_gray(ToneMapping::new(ToneMapOperator::Clamp, 1.0).apply(gray(0.25)), 0.5);
+        assert_gray(ToneMapping::new(ToneMapOperator::Clamp, -2.0).apply(gray(0.5)), 0.125);
+        // Con un paso más, Reinhard de 0.5 es Reinhard de 1
+        assert_gray(ToneMapping::new(ToneMapOperator::Reinhard, 1.0).apply(gray(0.5)), 0.5);
+    }
+
+    #[test]
+    fn srgb_round_trips_every_8_bit_value() {
+        for value in 0..=255 {
+            let color = Color::new(value, value, value);
+            assert_eq!(color.to_srgb8(), [value as u8; 3]);
+        }
+    }
+
+    #[test]
+    fn srgb_encoding_at_known_values() {
+        // Extremos, el tramo lineal cerca del negro y el gris medio lineal
+        assert_eq!(Color::new(0, 0, 0).r(), 0.0);
+        assert_eq!(Color::new(255, 255, 255).r(), 1.0);
+        assert!((Color::new(10, 10, 10).r() - 10.0 / 255.0 / 12.92).abs() < 1e-7);
+        assert!((Color::new(128, 128, 128).r() - 0.215_861).abs() < 1e-5);
+        assert_eq!(gray(0.5).to_srgb8(), [188; 3]);
+
+        // `to_hex` recorta fuera de [0, 1]
+        assert_eq!(Color::rgb(2.0, -1.0, 0.5).to_hex(), 0xff00bc);
+    }
+}
@@ -0,0 +1,405 @@
+// voxel.rs
+
+use nalgebra_glm::Vec3;
+use crate::bvh::Aabb;
+use crate::figures::box_uv;
+use crate::intersect::Intersect;
+use crate::material::Material;
+use crate::ray_intersect::Renderable;
+use std::sync::Arc;
+
+pub const AIR: u8 = 0;
+
+// Mundo de bloques unitarios guardados como IDs en un arreglo 3D denso. Los bloques se
+// identifican por las coordenadas enteras de su centro, igual que `Cube` con `length: 1`.
+pub struct VoxelGrid {
+    min: [i32; 3],
+    size: [usize; 3],
+    blocks: Vec<u8>,
+    palette: Vec<Arc<Material>>, // El ID `n` usa `palette[n - 1]`; el 0 es aire
+}
+
+impl VoxelGrid {
+    // Crea una cuadrícula vacía que cubre los bloques de `min` a `max` (ambos incluidos). Reserva
+    // un byte por celda: quien la llama debe limitar el tamaño.
+    pub fn new(min: [i32; 3], max: [i32; 3]) -> Self {
+        let size: [usize; 3] = std::array::from_fn(|axis| (max[axis] as i64 - min[axis] as i64 + 1).max(0) as usize);
+
+        VoxelGrid {
+            min,
+            size,
+            blocks: vec![AIR; size[0] * size[1] * size[2]],
+            palette: Vec::new(),
+        }
+    }
+
+    // Registra un material y devuelve el ID de bloque que lo representa
+    pub fn add_material(&mut self, material: Arc<Material>) -> u8 {
+        assert!(self.palette.len() < u8::MAX as usize, "VoxelGrid palette is full");
+        self.palette.push(material);
+        self.palette.len() as u8
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, z: i32, block: u8) {
+        if let Some(index) = self.index(x - self.min[0], y - self.min[1], z - self.min[2]) {
+            self.blocks[index] = block;
+        }
+    }
+
+    // Rellena la caja de bloques de `from` a `to` (ambos incluidos)
+    pub fn fill(&mut self, from: [i32; 3], to: [i32; 3], block: u8) {
+        for x in from[0].min(to[0])..=from[0].max(to[0]) {
+            for y in from[1].min(to[1])..=from[1].max(to[1]) {
+                for z in from[2].min(to[2])..=from[2].max(to[2]) {
+                    self.set(x, y, z, block);
+                }
+            }
+        }
+    }
+
+    // Índice lineal a partir de coordenadas locales de la cuadrícula
+    fn index(&self, x: i32, y: i32, z: i32) -> Option<usize> {
+        if x < 0 || y < 0 || z < 0 {
+            return None;
+        }
+        let (x, y, z) = (x as usize, y as usize, z as usize);
+        if x >= self.size[0] || y >= self.size[1] || z >= self.size[2] {
+            return None;
+        }
+        Some((y * self.size[2] + z) * self.size[0] + x)
+    }
+
+    fn block_at(&self, cell: &[i32; 3]) -> u8 {
+        self.index(cell[0], cell[1], cell[2]).map_or(AIR, |index| self.blocks[index])
+    }
+
+    // Esquina mínima del volumen que ocupa la cuadrícula en el espacio del mundo
+    fn lower_corner(&self) -> Vec3 {
+        Vec3::new(self.min[0] as f32 - 0.5, self.min[1] as f32 - 0.5, self.min[2] as f32 - 0.5)
+    }
+}
+
+impl Renderable for VoxelGrid {
+    // Recorrido de Amanatides–Woo: avanza celda por celda a lo largo del rayo
+    fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        if self.blocks.is_empty() {
+            return Intersect::empty();
+        }
+        // Sin dirección válida (NaN, infinita o nula) el recorrido no avanzaría nunca
+        if ray_direction.iter().any(|c| !c.is_finite()) || ray_direction.iter().all(|&c| c == 0.0) {
+            return Intersect::empty();
+        }
+
+        let bounds = self.bounding_box();
+        let inv_dir = Vec3::new(1.0 / ray_direction.x, 1.0 / ray_direction.y, 1.0 / ray_direction.z);
+
+        // Distancia a la que el rayo entra en la cuadrícula y eje de la cara por la que entra
+        let mut t_enter: f32 = 0.0;
+        let mut t_exit = f32::INFINITY;
+        let mut entry_axis = None;
+        for axis in 0..3 {
+            let t1 = (bounds.min[axis] - ray_origin[axis]) * inv_dir[axis];
+            let t2 = (bounds.max[axis] - ray_origin[axis]) * inv_dir[axis];
+            let (near, far) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+            if near > t_enter {
+                t_enter = near;
+                entry_axis = Some(axis);
+            }
+            t_exit = t_exit.min(far);
+        }
+
+        if t_enter > t_exit {
+            return Intersect::empty();
+        }
+
+        let lower = self.lower_corner();
+        let entry_point = ray_origin + ray_direction * t_enter;
+
+        let mut cell = [0i32; 3];
+        let mut step = [0i32; 3];
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+
+        for axis in 0..3 {
+            let local = entry_point[axis] - lower[axis];
+            cell[axis] = (local.floor() as i32).clamp(0, self.size[axis] as i32 - 1);
+
+            // Al entrar por una cara, la celda es la del lado interior de esa cara
+            if entry_axis == Some(axis) {
+                cell[axis] = if ray_direction[axis] > 0.0 { 0 } else { self.size[axis] as i32 - 1 };
+            }
+
+            if ray_direction[axis] > 0.0 {
+                step[axis] = 1;
+                let boundary = lower[axis] + (cell[axis] + 1) as f32;
+                t_max[axis] = (boundary - ray_origin[axis]) * inv_dir[axis];
+                t_delta[axis] = inv_dir[axis];
+            } else if ray_direction[axis] < 0.0 {
+                step[axis] = -1;
+                let boundary = lower[axis] + cell[axis] as f32;
+                t_max[axis] = (boundary - ray_origin[axis]) * inv_dir[axis];
+                t_delta[axis] = -inv_dir[axis];
+            }
+        }
+
+        let mut t = t_enter;
+        let mut hit_axis = entry_axis;
+
+        // Si el origen está dentro de un bloque, el rayo lo toca al salir (igual que en `Cube`).
+        // Esto permite que la refracción atraviese bloques como el agua.
+        let mut start_block = if entry_axis.is_none() { self.block_at(&cell) } else { AIR };
+
+        let hit = |cell: &[i32; 3], axis: usize, normal_sign: f32, t: f32, block: u8| {
+            let mut normal = Vec3::zeros();
+            normal[axis] = normal_sign;
+
+            let point = ray_origin + ray_direction * t;
+            let cell_min = lower + Vec3::new(cell[0] as f32, cell[1] as f32, cell[2] as f32);
+            let cell_max = cell_min + Vec3::new(1.0, 1.0, 1.0);
+            let (u, v) = box_uv(&cell_min, &cell_max, &point, &normal);
+
+            Intersect::new(
+                point,
+                normal,
+                t,
+                Some(self.palette[block as usize - 1].clone()),
+                u,
+                v,
+            )
+        };
+
+        // Cada paso cruza un borde de celda, así que ningún rayo da más pasos que la suma de los
+        // lados; el límite evita un bucle infinito si los errores de redondeo lo impiden
+        for _ in 0..self.size.iter().sum::<usize>() {
+            let block = self.block_at(&cell);
+
+            // Los rayos siguen a través de las zonas recortadas por el alfa de la textura
+            let mut entered_cut_out = false;
+            if start_block == AIR && block != AIR {
+                if let Some(axis) = hit_axis {
+                    let intersect = hit(&cell, axis, -step[axis] as f32, t, block);
+                    if !intersect.is_cut_out() {
+                        return intersect;
+                    }
+                    entered_cut_out = true;
+                }
+            }
+
+            // Avanzar hacia la siguiente celda por el eje cuyo borde está más cerca
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] { 0 } else { 2 }
+            } else if t_max[1] < t_max[2] {
+                1
+            } else {
+                2
+            };
+
+            let next_t = t_max[axis].min(t_exit);
+            let mut next_cell = cell;
+            next_cell[axis] += step[axis];
+            let leaves_grid = t_max[axis] > t_exit
+                || next_cell[axis] < 0
+                || next_cell[axis] >= self.size[axis] as i32;
+
+            // Salida del bloque inicial: la normal apunta hacia fuera del bloque. Si sale por una
+            // zona recortada, sigue como un rayo que empezó fuera.
+            if start_block != AIR && (leaves_grid || self.block_at(&next_cell) != start_block) {
+                let intersect = hit(&cell, axis, step[axis] as f32, next_t, start_block);
+                if !intersect.is_cut_out() {
+                    return intersect;
+                }
+                start_block = AIR;
+            }
+
+            // Si entró por una zona recortada, el rayo aún puede tocar la cara por la que sale del
+            // bloque, salvo que siga en otra celda del mismo bloque (como en `Cube`)
+            if entered_cut_out && (leaves_grid || self.block_at(&next_cell) != block) {
+                let intersect = hit(&cell, axis, step[axis] as f32, next_t, block);
+                if !intersect.is_cut_out() {
+                    return intersect;
+                }
+            }
+
+            if leaves_grid {
+                return Intersect::empty();
+            }
+
+            t = t_max[axis];
+            t_max[axis] += t_delta[axis];
+            cell = next_cell;
+            hit_axis = Some(axis);
+        }
+
+        Intersect::empty()
+    }
+
+    fn get_normal(&self, _point: &Vec3) -> Vec3 {
+        // La normal depende de la cara por la que entra el rayo; se calcula en `ray_intersect`
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let lower = self.lower_corner();
+        let extent = Vec3::new(self.size[0] as f32, self.size[1] as f32, self.size[2] as f32);
+        Aabb::new(lower, lower + extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::figures::Cube;
+    use crate::material::AlphaMode;
+    use crate::sampling::Rng;
+    use crate::texture::{Texel, Texture};
+
+    // Bloques sueltos, sin vecinos, para que cada uno sea exactamente un `Cube`
+    const BLOCKS: [[i32; 3]; 3] = [[1, 0, -1], [-1, 1, 1], [0, -1, 0]];
+
+    fn material() -> Arc<Material> {
+        Arc::new(Material::new(Color::rgb(0.5, 0.5, 0.5), 0.0, [1.0, 0.0, 0.0, 0.0], 1.0, Default::default(), false))
+    }
+
+    // Mismo material con un tablero de 4x4 texels alternando opacos y recortados en cada cara
+    fn masked_material() -> Arc<Material> {
+        let data = (0..16)
+            .map(|index: usize| Texel {
+                color: Color::rgb(0.5, 0.5, 0.5),
+                alpha: if (index % 4 + index / 4).is_multiple_of(2) { 1.0 } else { 0.0 },
+            })
+            .collect();
+        let texture = Arc::new(Texture::new(4, 4, data));
+
+        let mut material = (*material()).clone();
+        material.textures = std::array::from_fn(|_| Some(texture.clone()));
+        material.has_texture = true;
+        material.alpha_mode = AlphaMode::Mask;
+        Arc::new(material)
+    }
+
+    fn grid_and_cubes(material: Arc<Material>) -> (VoxelGrid, Vec<Cube>) {
+        let mut grid = VoxelGrid::new([-2, -2, -2], [2, 2, 2]);
+        let block = grid.add_material(material.clone());
+        let cubes = BLOCKS
+            .iter()
+            .map(|&[x, y, z]| {
+                grid.set(x, y, z, block);
+                Cube { center: Vec3::new(x as f32, y as f32, z as f32), length: 1, material: material.clone() }
+            })
+            .collect();
+        (grid, cubes)
+    }
+
+    fn nearest(cubes: &[Cube], origin: &Vec3, direction: &Vec3) -> Intersect {
+        let mut closest = Intersect::empty();
+        for cube in cubes {
+            let hit = cube.ray_intersect(origin, direction);
+            if hit.is_intersecting && (!closest.is_intersecting || hit.distance < closest.distance) {
+                closest = hit;
+            }
+        }
+        closest
+    }
+
+    fn assert_same_hit(grid: &VoxelGrid, cubes: &[Cube], origin: &Vec3, direction: &Vec3) -> bool {
+        let expected = nearest(cubes, origin, direction);
+        let actual = grid.ray_intersect(origin, direction);
+        let context = format!("origin {:?}, direction {:?}", origin, direction);
+
+        assert_eq!(actual.is_intersecting, expected.is_intersecting, "{}", context);
+        if expected.is_intersecting {
+            assert!((actual.distance - expected.distance).abs() < 1e-4, "{}", context);
+            assert!((actual.normal - expected.normal).norm() < 1e-6, "{}", context);
+            assert!((actual.u - expected.u).abs() < 1e-4 && (actual.v - expected.v).abs() < 1e-4, "{}", context);
+            assert_eq!(actual.uv_scale, expected.uv_scale, "{}", context);
+        }
+        expected.is_intersecting
+    }
+
+    #[test]
+    fn axis_aligned_rays_match_cube() {
+        let (grid, cubes) = grid_and_cubes(material());
+        for &[x, y, z] in &BLOCKS {
+            let center = Vec3::new(x as f32, y as f32, z as f32);
+            for axis in 0..3 {
+                for sign in [-1.0, 1.0] {
+                    let mut direction = Vec3::zeros();
+                    direction[axis] = -sign;
+                    // Desplazado dentro de la cara para que u y v no sean triviales
+                    let mut offset = Vec3::new(0.2, -0.3, 0.15);
+                    offset[axis] = sign * 6.0;
+                    assert!(assert_same_hit(&grid, &cubes, &(center + offset), &direction));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_and_axis_parallel_rays_terminate() {
+        let (grid, cubes) = grid_and_cubes(material());
+        let origin = Vec3::new(0.0, 10.0, 0.0);
+        for direction in [Vec3::new(f32::NAN, f32::NAN, f32::NAN), Vec3::new(0.0, f32::NAN, 0.0), Vec3::zeros()] {
+            assert!(!grid.ray_intersect(&origin, &direction).is_intersecting);
+        }
+
+        // Rayos verticales, con dos componentes nulas, sobre cada bloque y entre ellos
+        for x in -3..=3 {
+            for z in -3..=3 {
+                let origin = Vec3::new(x as f32 * 0.8, 10.0, z as f32 * 0.8);
+                assert_same_hit(&grid, &cubes, &origin, &Vec3::new(0.0, -1.0, 0.0));
+            }
+        }
+        let hit = grid.ray_intersect(&origin, &Vec3::new(0.0, -1.0, 0.0));
+        assert!(hit.is_intersecting && (hit.distance - 10.5).abs() < 1e-5);
+    }
+
+    // Lanza rayos al azar y devuelve cuántos tocan un bloque y cuántos de esos lo tocan al salir
+    fn compare_random_rays(grid: &VoxelGrid, cubes: &[Cube], seed: u64) -> (usize, usize) {
+        let mut rng = Rng::new(seed);
+        let mut random = |extent: f32| {
+            Vec3::new(
+                (rng.next_f32() * 2.0 - 1.0) * extent,
+                (rng.next_f32() * 2.0 - 1.0) * extent,
+                (rng.next_f32() * 2.0 - 1.0) * extent,
+            )
+        };
+
+        let (mut hits, mut exits) = (0, 0);
+        for i in 0..3000 {
+            // La mitad de los rayos sale de dentro de la cuadrícula (que cubre -2.5..2.5),
+            // incluido el interior de los bloques, y la otra mitad de fuera
+            let origin = if i % 2 == 0 { random(2.4) } else { random(8.0).map(|c| c + 3.0 * c.signum()) };
+            // La mitad de los rayos apunta cerca de un bloque, para que haya bastantes impactos
+            let direction = if i % 4 < 2 {
+                (random(1.0) + Vec3::new(0.0, 0.0, 1e-3)).normalize()
+            } else {
+                let [x, y, z] = BLOCKS[i % BLOCKS.len()];
+                (Vec3::new(x as f32, y as f32, z as f32) + random(0.6) - origin).normalize()
+            };
+            if assert_same_hit(grid, cubes, &origin, &direction) {
+                hits += 1;
+                if grid.ray_intersect(&origin, &direction).normal.dot(&direction) > 0.0 {
+                    exits += 1;
+                }
+            }
+        }
+        (hits, exits)
+    }
+
+    #[test]
+    fn random_rays_from_inside_and_outside_match_cube() {
+        let (grid, cubes) = grid_and_cubes(material());
+        let (hits, _) = compare_random_rays(&grid, &cubes, 3);
+        assert!(hits > 1000, "only {} rays hit a block", hits);
+    }
+
+    #[test]
+    fn masked_blocks_match_cube() {
+        let (grid, cubes) = grid_and_cubes(masked_material());
+        let (hits, exits) = compare_random_rays(&grid, &cubes, 5);
+        assert!(hits > 500, "only {} rays hit a block", hits);
+        // Rayos que entran por un texel recortado y tocan la cara opuesta, o que salen de dentro
+        assert!(exits > 100, "only {} rays hit a block from inside", exits);
+    }
+}
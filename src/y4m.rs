@@ -0,0 +1,126 @@
+// y4m.rs
+
+use std::fs::File;
+use std::io::{Write, BufWriter, Result};
+
+// Flujo de vídeo YUV4MPEG2 sin comprimir: una cabecera de texto y, por cada cuadro, la línea
+// `FRAME` seguida de los planos Y, U y V en 4:2:0. Los colores se convierten con BT.601 en
+// rango limitado (16-235), que es lo que ffmpeg y los reproductores asumen por defecto.
+pub struct Y4mWriter {
+    writer: BufWriter<File>,
+    width: usize,
+    height: usize,
+}
+
+impl Y4mWriter {
+    pub fn create(file_path: &str, width: usize, height: usize, fps: u32) -> Result<Self> {
+        let file = File::create(file_path)?;
+        let mut writer = BufWriter::new(file);
+
+        writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg", width, height, fps)?;
+
+        Ok(Y4mWriter { writer, width, height })
+    }
+
+    // Añade un cuadro a partir de píxeles 0xRRGGBB, fila por fila desde arriba
+    pub fn write_frame(&mut self, buffer: &[u32]) -> Result<()> {
+        let (width, height) = (self.width, self.height);
+        let chroma_width = width.div_ceil(2);
+        let chroma_height = height.div_ceil(2);
+
+        self.writer.write_all(b"FRAME\n")?;
+
+        let luma: Vec<u8> = buffer[..width * height].iter().map(|&pixel| {
+            let (r, g, b) = channels(pixel);
+            to_byte(16.0 + 0.257 * r + 0.504 * g + 0.098 * b)
+        }).collect();
+        self.writer.write_all(&luma)?;
+
+        // Cada muestra de croma es el promedio de un bloque de 2x2 píxeles; en los bordes
+        // impares el bloque se queda en los píxeles que existen
+        let mut cb = Vec::with_capacity(chroma_width * chroma_height);
+        let mut cr = Vec::with_capacity(chroma_width * chroma_height);
+        for cy in 0..chroma_height {
+            for cx in 0..chroma_width {
+                let (mut r, mut g, mut b, mut count) = (0.0, 0.0, 0.0, 0.0);
+                for y in (cy * 2)..(cy * 2 + 2).min(height) {
+                    for x in (cx * 2)..(cx * 2 + 2).min(width) {
+                        let (pr, pg, pb) = channels(buffer[y * width + x]);
+                        r += pr;
+                        g += pg;
+                        b += pb;
+                        count += 1.0;
+                    }
+                }
+                let (r, g, b) = (r / count, g / count, b / count);
+                cb.push(to_byte(128.0 - 0.148 * r - 0.291 * g + 0.439 * b));
+                cr.push(to_byte(128.0 + 0.439 * r - 0.368 * g - 0.071 * b));
+            }
+        }
+        self.writer.write_all(&cb)?;
+        self.writer.write_all(&cr)?;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn channels(pixel: u32) -> (f32, f32, f32) {
+    (
+        ((pixel >> 16) & 0xFF) as f32,
+        ((pixel >> 8) & 0xFF) as f32,
+        (pixel & 0xFF) as f32,
+    )
+}
+
+fn to_byte(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Escribe los cuadros en un archivo temporal y devuelve su contenido
+    fn encode(name: &str, width: usize, height: usize, frames: &[&[u32]]) -> Vec<u8> {
+        let dir = std::env::temp_dir().join(format!("y4m-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("video.y4m");
+
+        let mut writer = Y4mWriter::create(path.to_str().unwrap(), width, height, 24).unwrap();
+        for frame in frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn solid_frames_have_known_bt601_values() {
+        let bytes = encode("solid", 2, 2, &[&[0xff0000; 4], &[0xffffff; 4]]);
+
+        // Rojo puro: Y = 16 + 0.257 * 255, Cb = 128 - 0.148 * 255, Cr = 128 + 0.439 * 255.
+        // El blanco queda en el máximo del rango limitado y sin croma.
+        let mut expected = b"YUV4MPEG2 W2 H2 F24:1 Ip A1:1 C420jpeg\n".to_vec();
+        expected.extend_from_slice(b"FRAME\n");
+        expected.extend_from_slice(&[82, 82, 82, 82, 90, 240]);
+        expected.extend_from_slice(b"FRAME\n");
+        expected.extend_from_slice(&[235, 235, 235, 235, 128, 128]);
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn chroma_averages_the_pixels_of_each_block() {
+        // Con ancho impar el último bloque de croma sólo tiene el píxel blanco
+        let bytes = encode("odd", 3, 1, &[&[0xff0000, 0x0000ff, 0xffffff]]);
+        let header = b"YUV4MPEG2 W3 H1 F24:1 Ip A1:1 C420jpeg\nFRAME\n";
+        assert_eq!(&bytes[..header.len()], header);
+        // Y de rojo, azul y blanco; después Cb y Cr del promedio de rojo y azul, y del blanco
+        assert_eq!(&bytes[header.len()..], &[82, 41, 235, 165, 128, 175, 128]);
+    }
+}